@@ -0,0 +1,239 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use crate::config::{config_schema, validate_config, Config, ConfigSource};
+use crate::core::Gateway;
+
+/// Command line interface of the gateway binary.
+///
+/// Flags are the last configuration layer and win over the config file and
+/// `RUSTOPUS__*` environment variables.
+#[derive(Debug, Parser)]
+#[command(name = "rustopus", version, about = env!("CARGO_PKG_DESCRIPTION"), long_about = None)]
+pub struct Cli {
+    /// Path to the configuration file (JSON or YAML)
+    #[arg(short, long, global = true, env = "GATEWAY_CONFIG", default_value = "gateway-config.json")]
+    pub config: PathBuf,
+
+    /// Address to bind the HTTP server to
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+
+    /// Port to bind the HTTP server to
+    #[arg(short, long, global = true)]
+    pub port: Option<u16>,
+
+    /// Number of worker threads
+    #[arg(long, global = true)]
+    pub workers: Option<usize>,
+
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+
+    /// Override any setting by its dotted path, e.g. `--set metrics.enabled=false`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub overrides: Vec<(String, String)>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the gateway (the default when no command is given)
+    Serve,
+    /// Load and validate a configuration, exiting non-zero when it is invalid
+    Validate {
+        /// Configuration file to check; defaults to `--config`
+        file: Option<PathBuf>,
+    },
+    /// Print the effective configuration with secrets redacted
+    PrintConfig {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Yaml)]
+        format: OutputFormat,
+    },
+    /// List the routes compiled from the configuration
+    Routes,
+    /// Print the JSON Schema of the configuration file
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Yaml,
+    Json,
+}
+
+impl Cli {
+    /// The configuration layers selected on the command line.
+    pub fn config_source(&self) -> ConfigSource {
+        let mut source = ConfigSource::new(&self.config);
+        if let Some(host) = &self.host {
+            source = source.with_override("server.host", host);
+        }
+        if let Some(port) = self.port {
+            source = source.with_override("server.port", port.to_string());
+        }
+        if let Some(workers) = self.workers {
+            source = source.with_override("server.workers", workers.to_string());
+        }
+        if let Some(level) = &self.log_level {
+            source = source.with_override("logging.level", level);
+        }
+        source.overrides.extend(self.overrides.iter().cloned());
+        source
+    }
+}
+
+/// Implements `rustopus validate`.
+pub fn validate(mut source: ConfigSource, file: Option<PathBuf>) -> ExitCode {
+    if let Some(file) = file {
+        source.path = file;
+    }
+
+    let config = match source.load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}: {:#}", source.path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match validate_config(&config) {
+        Ok(()) => {
+            let count = config.endpoints.len();
+            println!(
+                "{}: configuration is valid ({} endpoint{})",
+                source.path.display(),
+                count,
+                if count == 1 { "" } else { "s" }
+            );
+            ExitCode::SUCCESS
+        }
+        Err(errors) => {
+            eprintln!("{}: {}", source.path.display(), errors);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Implements `rustopus print-config`.
+pub fn print_config(source: &ConfigSource, format: OutputFormat) -> Result<ExitCode> {
+    let config = Config::load_from(source)?;
+    let output = match format {
+        OutputFormat::Yaml => serde_yaml::to_string(&config)?,
+        OutputFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+    };
+    print!("{}", output);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Implements `rustopus routes`.
+pub async fn routes(source: &ConfigSource) -> Result<ExitCode> {
+    let config = Config::load_from(source)?;
+    let gateway = Gateway::new(
+        "rustopus".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+        config,
+    )?;
+    gateway.init().await?;
+
+    let http = gateway.http_protocol();
+    let http = http.read().await;
+    let mut rows: Vec<[String; 3]> = http
+        .router_ref()
+        .routes()
+        .iter()
+        .map(|route| {
+            let backends: Vec<_> = route.config.backend.iter().map(|b| b.url.as_str()).collect();
+            [route.method.to_string(), route.config.path.clone(), backends.join(", ")]
+        })
+        .collect();
+    rows.sort_by(|a, b| a[1].cmp(&b[1]).then_with(|| a[0].cmp(&b[0])));
+
+    let mut stdout = std::io::stdout().lock();
+    write_table(&mut stdout, ["METHOD", "PATH", "BACKENDS"], &rows)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Implements `rustopus schema`.
+pub fn schema() -> Result<ExitCode> {
+    println!("{}", serde_json::to_string_pretty(&config_schema())?);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write_table<const N: usize>(out: &mut impl Write, header: [&str; N], rows: &[[String; N]]) -> Result<()> {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let header = header.map(str::to_string);
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_become_overrides() {
+        let cli = Cli::parse_from([
+            "rustopus",
+            "--config",
+            "gateway.yaml",
+            "--port",
+            "8080",
+            "--set",
+            "metrics.enabled=false",
+        ]);
+        let source = cli.config_source();
+
+        assert_eq!(source.path, PathBuf::from("gateway.yaml"));
+        assert_eq!(
+            source.overrides,
+            vec![
+                ("server.port".to_string(), "8080".to_string()),
+                ("metrics.enabled".to_string(), "false".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let cli = Cli::parse_from(["rustopus", "validate", "gateway.yaml", "--port", "8080"]);
+        assert!(matches!(cli.command, Some(Command::Validate { file: Some(ref f) }) if f == &PathBuf::from("gateway.yaml")));
+        assert_eq!(cli.port, Some(8080));
+
+        let cli = Cli::parse_from(["rustopus"]);
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_write_table() {
+        let mut out = Vec::new();
+        let rows = [["GET".to_string(), "/users".to_string()]];
+        write_table(&mut out, ["METHOD", "PATH"], &rows).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "METHOD  PATH\nGET     /users\n");
+    }
+}
@@ -0,0 +1,223 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::time::Duration;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+/// Parses a human-friendly duration such as `250ms`, `5s`, `2m` or `1h30m`.
+///
+/// A bare integer is read as whole seconds, which keeps configs written
+/// before unit suffixes were supported working unchanged. Supported units
+/// are `ns`, `us`, `ms`, `s`, `m`, `h` and `d`.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err("empty duration".to_string());
+    }
+
+    if let Ok(secs) = trimmed.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!("invalid duration `{}`: expected a number", input));
+        }
+        let amount: u64 = rest[..digits]
+            .parse()
+            .map_err(|_| format!("invalid duration `{}`: number too large", input))?;
+        rest = &rest[digits..];
+
+        let unit_len = rest.find(|c: char| c.is_ascii_digit() || c.is_whitespace()).unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        rest = rest[unit_len..].trim_start();
+
+        let component = match unit {
+            "ns" => Duration::from_nanos(amount),
+            "us" | "µs" => Duration::from_micros(amount),
+            "ms" => Duration::from_millis(amount),
+            "s" => Duration::from_secs(amount),
+            "m" => amount.checked_mul(60).map(Duration::from_secs).ok_or("duration overflow")?,
+            "h" => amount.checked_mul(3600).map(Duration::from_secs).ok_or("duration overflow")?,
+            "d" => amount.checked_mul(86400).map(Duration::from_secs).ok_or("duration overflow")?,
+            "" => return Err(format!("invalid duration `{}`: missing unit after {}", input, amount)),
+            other => return Err(format!("invalid duration `{}`: unknown unit `{}`", input, other)),
+        };
+        total = total
+            .checked_add(component)
+            .ok_or_else(|| format!("invalid duration `{}`: overflow", input))?;
+    }
+
+    Ok(total)
+}
+
+/// Formats a duration in the same notation accepted by [`parse_duration`],
+/// e.g. `1h30m` or `1s250ms`.
+pub fn format_duration(duration: Duration) -> String {
+    if duration.is_zero() {
+        return "0s".to_string();
+    }
+
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    let parts = [
+        (secs / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+        (u64::from(nanos / 1_000_000), "ms"),
+        (u64::from(nanos / 1_000 % 1_000), "us"),
+        (u64::from(nanos % 1_000), "ns"),
+    ];
+
+    parts
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect()
+}
+
+/// Deserialization helper accepting either integer seconds or a duration string.
+pub(crate) struct HumanDuration(Duration);
+
+impl JsonSchema for HumanDuration {
+    fn schema_name() -> Cow<'static, str> {
+        "Duration".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A duration such as `250ms`, `5s`, `2m` or `1h30m`, or a number of seconds",
+            "anyOf": [
+                { "type": "string", "pattern": "^\\s*(\\d+|(\\d+(ns|us|µs|ms|s|m|h|d)\\s*)+)$" },
+                { "type": "integer", "minimum": 0 }
+            ]
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HumanDurationVisitor;
+
+        impl Visitor<'_> for HumanDurationVisitor {
+            type Value = HumanDuration;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a duration such as `30s`, `250ms` or `1h30m`, or integer seconds")
+            }
+
+            fn visit_u64<E: de::Error>(self, secs: u64) -> Result<Self::Value, E> {
+                Ok(HumanDuration(Duration::from_secs(secs)))
+            }
+
+            fn visit_i64<E: de::Error>(self, secs: i64) -> Result<Self::Value, E> {
+                u64::try_from(secs)
+                    .map(|secs| HumanDuration(Duration::from_secs(secs)))
+                    .map_err(|_| E::custom("duration cannot be negative"))
+            }
+
+            fn visit_f64<E: de::Error>(self, secs: f64) -> Result<Self::Value, E> {
+                Duration::try_from_secs_f64(secs)
+                    .map(HumanDuration)
+                    .map_err(|_| E::custom("duration must be a non-negative number of seconds"))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                parse_duration(value).map(HumanDuration).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(HumanDurationVisitor)
+    }
+}
+
+pub mod duration_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+    use super::{format_duration, HumanDuration};
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        format_duration(*duration).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        HumanDuration::deserialize(deserializer).map(|d| d.0)
+    }
+}
+
+pub mod option_duration_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+    use super::{format_duration, HumanDuration};
+
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match duration {
+            Some(d) => format_duration(*d).serialize(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let duration = Option::<HumanDuration>::deserialize(deserializer)?;
+        Ok(duration.map(|d| d.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = vec![
+            ("30", Duration::from_secs(30)),
+            ("250ms", Duration::from_millis(250)),
+            ("5s", Duration::from_secs(5)),
+            ("2m", Duration::from_secs(120)),
+            ("1h30m", Duration::from_secs(5400)),
+            ("1h 30m", Duration::from_secs(5400)),
+            ("1s500ms", Duration::from_millis(1500)),
+            ("1d", Duration::from_secs(86400)),
+            ("0s", Duration::ZERO),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse_duration(input).unwrap(), expected, "parsing {}", input);
+        }
+
+        for input in ["", "ms", "5x", "-5s", "1.5s", "10s5"] {
+            assert!(parse_duration(input).is_err(), "{} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        for duration in [
+            Duration::ZERO,
+            Duration::from_millis(250),
+            Duration::from_secs(5400),
+            Duration::from_millis(90_061_001),
+        ] {
+            assert_eq!(parse_duration(&format_duration(duration)).unwrap(), duration);
+        }
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+    }
+}
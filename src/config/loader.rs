@@ -1,16 +1,285 @@
-use std::path::Path;
-use anyhow::{Result, Context};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Result, Context};
+use serde_json::Value;
 use tracing::{info, warn};
 use super::Config;
 
+/// Prefix for environment overrides, e.g. `RUSTOPUS__SERVER__PORT=8080`.
+pub const ENV_PREFIX: &str = "RUSTOPUS";
+const ENV_SEPARATOR: &str = "__";
+
+/// Extensions recognised for the config file and for included fragments.
+const EXTENSIONS: [&str; 4] = ["json", "yaml", "yml", "toml"];
+
+/// Describes where the effective configuration comes from. Layers are applied
+/// in order, later ones winning: `Config::default()`, the config file (with
+/// the endpoints of its `include`s merged in), `RUSTOPUS__*` environment
+/// variables and finally explicit overrides (typically command-line flags)
+/// given as dotted keys like `server.port`.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub overrides: Vec<(String, String)>,
+}
+
+impl ConfigSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            overrides: Vec::new(),
+        }
+    }
+
+    pub fn with_override(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn load(&self) -> Result<Config> {
+        self.load_with_env(None)
+    }
+
+    /// The files and directories listed under `include` in the config file,
+    /// resolved relative to it.
+    pub fn includes(&self) -> Result<Vec<PathBuf>> {
+        match read_file(&self.path)? {
+            Some(file) => include_paths(&self.path, &file),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn load_with_env(&self, env: Option<HashMap<String, String>>) -> Result<Config> {
+        info!("Loading configuration...");
+        info!("Path: {}", self.path.display());
+
+        // Layers are merged as plain trees so that map keys containing dots,
+        // like `app.kubernetes.io/name` in metric tags, stay single keys
+        let mut tree = serde_json::to_value(Config::default())?;
+
+        if let Some(mut file) = read_file(&self.path)? {
+            merge_includes(&self.path, &mut file)?;
+            merge(&mut tree, file);
+        }
+
+        let env = env.unwrap_or_else(|| std::env::vars().collect());
+        let mut env: Vec<_> = env
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let key = key.strip_prefix(ENV_PREFIX)?.strip_prefix(ENV_SEPARATOR)?.to_lowercase();
+                Some((key, value))
+            })
+            .collect();
+        // Sorted so that conflicting variables resolve the same way every time
+        env.sort();
+        for (key, value) in &env {
+            set(&mut tree, key.split(ENV_SEPARATOR), value)
+                .with_context(|| format!("Invalid environment override {}{}{}", ENV_PREFIX, ENV_SEPARATOR, key.to_uppercase()))?;
+        }
+
+        for (key, value) in &self.overrides {
+            set(&mut tree, key.split('.'), value)
+                .with_context(|| format!("Invalid override for {}", key))?;
+        }
+
+        serde_json::from_value(tree)
+            .with_context(|| format!("Failed to load configuration from {}", self.path.display()))
+    }
+}
+
+/// Deep-merges `layer` into `base`: objects merge key by key, any other value
+/// replaces what was there, and nulls leave the lower layer in place.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (_, Value::Null) => {}
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Sets the value at `path` from its textual form, creating objects along the
+/// way. Strings stay strings where the lower layers have a string; otherwise
+/// the text is read as JSON (numbers, booleans, lists) and falls back to a
+/// plain string.
+fn set<'a>(tree: &mut Value, path: impl Iterator<Item = &'a str>, value: &str) -> Result<()> {
+    let mut node = tree;
+    for segment in path {
+        if segment.is_empty() {
+            bail!("empty key segment");
+        }
+        if !node.is_object() {
+            *node = Value::Object(Default::default());
+        }
+        node = node
+            .as_object_mut()
+            .expect("node was just made an object")
+            .entry(segment)
+            .or_insert(Value::Null);
+    }
+
+    *node = match node {
+        Value::String(_) => Value::String(value.to_string()),
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+    };
+    Ok(())
+}
+
 pub fn load_config(path: &Path) -> Result<Config> {
-    info!("Loading configuration...");
-    info!("Path: {}", path.display());
-    info!("Exists: {}", path.exists());
+    ConfigSource::new(path).load()
+}
+
+/// Whether `path` looks like a config file that can be loaded or included.
+pub(super) fn is_config_file(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    !hidden && extension.is_some_and(|ext| EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn include_paths(config_path: &Path, file: &Value) -> Result<Vec<PathBuf>> {
+    let Some(include) = file.get("include") else {
+        return Ok(Vec::new());
+    };
+    let entries: Vec<String> = serde_json::from_value(include.clone())
+        .with_context(|| format!("`include` in {} must be a list of paths", config_path.display()))?;
+
+    let base = config_path.parent().unwrap_or(Path::new(""));
+    Ok(entries.iter().map(|entry| base.join(entry)).collect())
+}
+
+/// Expands an include entry into the fragment files it names: the file itself,
+/// or every config file in a directory sorted by name.
+fn fragment_files(include: &Path) -> Result<Vec<PathBuf>> {
+    if !include.exists() {
+        bail!("Included path {} does not exist", include.display());
+    }
+    if !include.is_dir() {
+        return Ok(vec![include.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(include)
+        .with_context(|| format!("Failed to read directory {}", include.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() && is_config_file(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the endpoints contributed by a fragment, which is either a list of
+/// endpoints or a map whose only key is `endpoints`.
+fn read_fragment(path: &Path) -> Result<Vec<Value>> {
+    let endpoints = match read_file(path)? {
+        None => return Ok(Vec::new()),
+        Some(Value::Object(mut fragment)) => {
+            let endpoints = fragment.remove("endpoints");
+            if let Some(key) = fragment.keys().next() {
+                bail!(
+                    "Unexpected key `{}` in {}: included files may only define `endpoints`",
+                    key,
+                    path.display()
+                );
+            }
+            endpoints.unwrap_or(Value::Null)
+        }
+        Some(endpoints) => endpoints,
+    };
+
+    match endpoints {
+        Value::Array(endpoints) => Ok(endpoints),
+        Value::Null => Ok(Vec::new()),
+        _ => bail!("`endpoints` in {} must be a list", path.display()),
+    }
+}
+
+/// Appends the endpoints of every included fragment to the config file's own,
+/// in include order, rejecting any method and path defined twice.
+fn merge_includes(config_path: &Path, file: &mut Value) -> Result<()> {
+    let includes = include_paths(config_path, file)?;
+    let Value::Object(map) = file else {
+        return Ok(());
+    };
+
+    let mut endpoints = match map.remove("endpoints") {
+        Some(Value::Array(endpoints)) => endpoints,
+        None | Some(Value::Null) => Vec::new(),
+        Some(_) => bail!("`endpoints` in {} must be a list", config_path.display()),
+    };
+
+    let mut defined_in = HashMap::new();
+    check_duplicates(&mut defined_in, &endpoints, config_path)?;
+    for include in includes {
+        for fragment in fragment_files(&include)? {
+            let fragment_endpoints = read_fragment(&fragment)?;
+            check_duplicates(&mut defined_in, &fragment_endpoints, &fragment)?;
+            endpoints.extend(fragment_endpoints);
+        }
+    }
+
+    if !endpoints.is_empty() {
+        map.insert("endpoints".to_string(), Value::Array(endpoints));
+    }
+    Ok(())
+}
+
+fn check_duplicates(
+    defined_in: &mut HashMap<(String, String, String), PathBuf>,
+    endpoints: &[Value],
+    source: &Path,
+) -> Result<()> {
+    for endpoint in endpoints {
+        let method = endpoint.get("method").and_then(Value::as_str);
+        let path = endpoint.get("path").and_then(Value::as_str);
+        // Entries missing either field are reported by deserialization
+        let (Some(method), Some(path)) = (method, path) else {
+            continue;
+        };
+        // Endpoints sharing a method and path are distinct when their
+        // request conditions differ
+        let matches = endpoint.get("matches").map(Value::to_string).unwrap_or_default();
+
+        match defined_in.entry((method.to_uppercase(), path.to_string(), matches)) {
+            Entry::Occupied(first) => bail!(
+                "Duplicate endpoint {} {} in {} (already defined in {})",
+                method.to_uppercase(),
+                path,
+                source.display(),
+                first.get().display()
+            ),
+            Entry::Vacant(entry) => {
+                entry.insert(source.to_path_buf());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a config file into a generic tree, or returns `None` when the file
+/// layer should be skipped and only defaults and overrides apply.
+fn read_file(path: &Path) -> Result<Option<Value>> {
     if !path.exists() {
         info!("No config file found at {}, using default configuration", path.display());
-        info!("Exists: {}", path.exists());
-        return Ok(Config::default());
+        return Ok(None);
     }
 
     if path.is_dir() {
@@ -23,7 +292,7 @@ pub fn load_config(path: &Path) -> Result<Config> {
 
     if contents.trim().is_empty() {
         warn!("Config file {} is empty, using default configuration", path.display());
-        return Ok(Config::default());
+        return Ok(None);
     }
 
     match path.extension()
@@ -34,13 +303,21 @@ pub fn load_config(path: &Path) -> Result<Config> {
         Some("json") => {
             info!("Loading JSON config from {}", path.display());
             serde_json::from_str(&contents)
+                .map(Some)
                 .with_context(|| format!("Failed to parse JSON config from {}", path.display()))
         }
         Some("yaml") | Some("yml") => {
             info!("Loading YAML config from {}", path.display());
             serde_yaml::from_str(&contents)
+                .map(Some)
                 .with_context(|| format!("Failed to parse YAML config from {}", path.display()))
         }
+        Some("toml") => {
+            info!("Loading TOML config from {}", path.display());
+            toml::from_str(&contents)
+                .map(Some)
+                .with_context(|| format!("Failed to parse TOML config from {}", path.display()))
+        }
         Some(ext) => {
             info!("Unsupported config file format: .{}", ext);
             Err(anyhow::anyhow!("Unsupported config file format: .{}", ext))
@@ -49,4 +326,96 @@ pub fn load_config(path: &Path) -> Result<Config> {
             Err(anyhow::anyhow!("Config file {} has no extension", path.display()))
         }
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rustopus-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_partial_file_falls_back_to_defaults() {
+        let path = write_config("partial.yaml", "server:\n  port: 8081\n");
+        let config = ConfigSource::new(&path).load_with_env(Some(HashMap::new())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.port, 8081);
+        assert_eq!(config.server.host, Config::default().server.host);
+        assert_eq!(config.metrics.path, "/metrics");
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let path = write_config("layers.json", r#"{"server": {"port": 8081, "workers": 2}}"#);
+        let env = HashMap::from([
+            ("RUSTOPUS__SERVER__PORT".to_string(), "8082".to_string()),
+            ("RUSTOPUS__SERVER__HOST".to_string(), "0.0.0.0".to_string()),
+        ]);
+        let config = ConfigSource::new(&path)
+            .with_override("server.port", "8083")
+            .load_with_env(Some(env))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.workers, 2);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 8083);
+    }
+
+    #[test]
+    fn test_dotted_map_keys() {
+        let path = write_config(
+            "dotted.yaml",
+            "metrics:\n  tags:\n    app.kubernetes.io/name: gateway\n",
+        );
+        let env = HashMap::from([("RUSTOPUS__METRICS__TAGS__REGION".to_string(), "eu".to_string())]);
+        let config = ConfigSource::new(&path)
+            .with_override("metrics.enabled", "false")
+            .load_with_env(Some(env))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.metrics.tags["app.kubernetes.io/name"], "gateway");
+        assert_eq!(config.metrics.path, "/metrics");
+        assert!(!config.metrics.enabled);
+        assert_eq!(config.metrics.tags["region"], "eu");
+    }
+
+    #[test]
+    fn test_includes_merge_in_order() {
+        let dir = std::env::temp_dir().join(format!("rustopus-{}-includes", std::process::id()));
+        let fragments = dir.join("conf.d");
+        std::fs::create_dir_all(&fragments).unwrap();
+        std::fs::write(
+            dir.join("gateway.yaml"),
+            "include: [conf.d, extra.toml]\nendpoints:\n  - { path: /a, method: GET, backend: [{ url: 'http://b' }] }\n",
+        )
+        .unwrap();
+        std::fs::write(fragments.join("20-c.json"), r#"[{"path": "/c", "method": "GET", "backend": [{"url": "http://b"}]}]"#).unwrap();
+        std::fs::write(fragments.join("10-b.yaml"), "endpoints:\n  - { path: /b, method: GET, backend: [{ url: 'http://b' }] }\n").unwrap();
+        std::fs::write(fragments.join("notes.txt"), "not a fragment").unwrap();
+        std::fs::write(
+            dir.join("extra.toml"),
+            "[[endpoints]]\npath = \"/d\"\nmethod = \"POST\"\nbackend = [{ url = 'http://b' }]\n",
+        )
+        .unwrap();
+
+        let source = ConfigSource::new(dir.join("gateway.yaml"));
+        let config = source.load_with_env(Some(HashMap::new())).unwrap();
+        let paths: Vec<_> = config.endpoints.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/a", "/b", "/c", "/d"]);
+        assert_eq!(source.includes().unwrap(), [dir.join("conf.d"), dir.join("extra.toml")]);
+
+        std::fs::write(fragments.join("30-dup.yaml"), "- { path: /b, method: get, backend: [{ url: 'http://b' }] }\n").unwrap();
+        let error = source.load_with_env(Some(HashMap::new())).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error.contains("Duplicate endpoint GET /b"), "{}", error);
+        assert!(error.contains("10-b.yaml"), "{}", error);
+    }
+}
@@ -1,7 +1,16 @@
+mod duration;
 mod loader;
+mod schema;
+mod secret;
 mod validator;
+mod watcher;
+pub mod template;
 pub mod types;
 
-pub use loader::load_config;
-pub use validator::validate_config;
-pub use types::Config; 
\ No newline at end of file
+pub use duration::{format_duration, parse_duration};
+pub use schema::config_schema;
+pub use secret::Secret;
+pub use loader::{load_config, ConfigSource, ENV_PREFIX};
+pub use validator::{validate_config, ValidationError, ValidationErrors};
+pub use watcher::ConfigWatcher;
+pub use types::Config;
\ No newline at end of file
@@ -0,0 +1,76 @@
+use serde_json::{Map, Value};
+use super::Config;
+
+/// Generates a JSON Schema describing the whole configuration tree.
+///
+/// Besides the shape of every section and the allowed enum values, each
+/// property is annotated with the value it takes when omitted, taken from
+/// `Config::default()`, so editors can show it on hover and autocomplete.
+pub fn config_schema() -> Value {
+    let mut schema = schemars::schema_for!(Config).to_value();
+    let defaults = serde_json::to_value(Config::default())
+        .expect("default configuration is always serializable");
+
+    if let Value::Object(root) = &mut schema {
+        let mut definitions = match root.remove("$defs") {
+            Some(Value::Object(definitions)) => definitions,
+            _ => Map::new(),
+        };
+        apply_defaults(root, &defaults, &mut definitions);
+        root.insert("$defs".to_string(), Value::Object(definitions));
+    }
+
+    schema
+}
+
+/// Copies default values into the `properties` of `schema`, following `$ref`s
+/// into the shared definitions so nested fields are annotated as well. A
+/// definition reused in several places keeps the first default it receives.
+fn apply_defaults(schema: &mut Map<String, Value>, defaults: &Value, definitions: &mut Map<String, Value>) {
+    let Some(Value::Object(properties)) = schema.get_mut("properties") else {
+        return;
+    };
+
+    for (name, property) in properties.iter_mut() {
+        let (Some(default), Value::Object(property)) = (defaults.get(name), property) else {
+            continue;
+        };
+        property.entry("default").or_insert_with(|| default.clone());
+
+        let reference = property
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|r| r.strip_prefix("#/$defs/"))
+            .map(str::to_string);
+        if let Some(reference) = reference {
+            // Taking the definition out while descending also stops recursive types
+            if let Some(Value::Object(mut definition)) = definitions.remove(&reference) {
+                apply_defaults(&mut definition, default, definitions);
+                definitions.insert(reference, Value::Object(definition));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+        let definitions = &schema["$defs"];
+
+        assert_eq!(schema["type"], "object");
+        assert_eq!(
+            definitions["BackendProtocol"]["enum"],
+            serde_json::json!(["rest", "grpc", "websocket"])
+        );
+        assert_eq!(definitions["ServerConfig"]["properties"]["port"]["default"], 3000);
+        assert_eq!(definitions["ServerConfig"]["properties"]["timeout"]["default"], "30s");
+        assert!(definitions["EndpointConfig"]["required"]
+            .as_array()
+            .unwrap()
+            .contains(&Value::from("path")));
+    }
+}
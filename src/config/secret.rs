@@ -0,0 +1,155 @@
+use std::borrow::Cow;
+use std::fmt;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const REDACTED: &str = "<redacted>";
+
+/// A sensitive config value such as a JWT signing key or client secret.
+///
+/// In the config file it is written either in plaintext or with references
+/// like `${env:JWT_SECRET}` or `${file:/run/secrets/jwt}`, which are resolved
+/// when the configuration is loaded, so also on every reload. The resolved
+/// value is only reachable through [`Secret::expose`]: `Debug` prints a
+/// placeholder and serialization writes back the original reference, or the
+/// placeholder for plaintext values.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret {
+    value: String,
+    /// The value as written in the config, when it contained references.
+    reference: Option<String>,
+}
+
+impl Secret {
+    /// Resolves every `${env:NAME}` and `${file:PATH}` reference in `raw`.
+    pub fn resolve(raw: &str) -> Result<Self, String> {
+        if !raw.contains("${") {
+            return Ok(Self::from(raw));
+        }
+
+        let mut value = String::new();
+        let mut rest = raw;
+        while let Some(start) = rest.find("${") {
+            value.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unterminated secret reference in `{}`", raw))?;
+            value.push_str(&resolve_reference(&rest[start + 2..end])?);
+            rest = &rest[end + 1..];
+        }
+        value.push_str(rest);
+
+        Ok(Self {
+            value,
+            reference: Some(raw.to_string()),
+        })
+    }
+
+    /// The resolved secret value.
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+}
+
+fn resolve_reference(reference: &str) -> Result<String, String> {
+    match reference.split_once(':') {
+        Some(("env", name)) => std::env::var(name)
+            .map_err(|_| format!("environment variable `{}` referenced by a secret is not set", name)),
+        Some(("file", path)) => std::fs::read_to_string(path)
+            // Secret files are usually written with a trailing newline
+            .map(|contents| contents.trim_end_matches(['\r', '\n']).to_string())
+            .map_err(|e| format!("failed to read secret file `{}`: {}", path, e)),
+        _ => Err(format!(
+            "unsupported secret reference `${{{}}}`, expected `${{env:NAME}}` or `${{file:PATH}}`",
+            reference
+        )),
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            reference: None,
+        }
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reference {
+            Some(reference) => write!(f, "Secret({:?})", reference),
+            None => write!(f, "Secret({:?})", REDACTED),
+        }
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.reference.as_deref().unwrap_or(REDACTED).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        Secret::resolve(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for Secret {
+    fn schema_name() -> Cow<'static, str> {
+        "Secret".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A secret in plaintext or, preferably, a reference such as `${env:JWT_SECRET}` or `${file:/run/secrets/jwt}`",
+            "type": "string"
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_references() {
+        let path = std::env::temp_dir().join(format!("rustopus-{}-secret", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        std::env::set_var("RUSTOPUS_TEST_SECRET", "from-env");
+
+        let raw = format!("${{env:RUSTOPUS_TEST_SECRET}}:${{file:{}}}", path.display());
+        let secret = Secret::resolve(&raw).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(secret.expose(), "from-env:from-file");
+        assert_eq!(serde_json::to_value(&secret).unwrap(), raw.as_str());
+        assert!(!format!("{:?}", secret).contains("from-env"));
+
+        assert_eq!(Secret::resolve("plain").unwrap().expose(), "plain");
+        assert!(Secret::resolve("${env:RUSTOPUS_TEST_UNSET_SECRET}").is_err());
+        assert!(Secret::resolve("${vault:jwt}").is_err());
+        assert!(Secret::resolve("${env:JWT").is_err());
+    }
+
+    #[test]
+    fn test_plaintext_is_never_printed() {
+        let secret: Secret = serde_json::from_str(r#""hunter2""#).unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(format!("{:?}", secret), r#"Secret("<redacted>")"#);
+        assert_eq!(serde_json::to_string(&secret).unwrap(), r#""<redacted>""#);
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{bail, Result};
+
+/// Parameter under which a trailing wildcard, named or not, captures the
+/// rest of the path. It is internal to the gateway and not passed on to
+/// middleware.
+pub const WILDCARD_PARAM: &str = "*";
+
+/// Names that can be used as `{{name}}` in error templates.
+pub const TEMPLATE_FIELDS: [&str; 6] = ["status", "title", "detail", "type", "instance", "request_id"];
+
+/// Replaces every `{name}` in the backend URL `template` with `value(name)`.
+/// Fails on unknown names and unbalanced braces.
+pub fn expand_template(template: &str, value: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            bail!("Unbalanced '}}' in backend URL {}", template);
+        }
+        let Some(len) = rest[start..].find('}') else {
+            bail!("Unbalanced '{{' in backend URL {}", template);
+        };
+        let name = &rest[start + 1..start + len];
+        let Some(replacement) = value(name) else {
+            bail!("Backend URL {} uses unknown parameter {{{}}}", template, name);
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&replacement);
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Replaces every `{{name}}` in the error template `template` with
+/// `value(name)`. Fails on unknown names and unclosed placeholders.
+pub fn render(template: &str, value: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            bail!("Unclosed '{{{{' in error template");
+        };
+        let name = rest[start + 2..start + len].trim();
+        let Some(replacement) = value(name) else {
+            bail!("Error template uses unknown field {{{{{}}}}}", name);
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&replacement);
+        rest = &rest[start + len + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        let value = |name: &str| (name == "id").then(|| "42".to_string());
+        assert_eq!(expand_template("/users/{id}/posts", value).unwrap(), "/users/42/posts");
+        assert!(expand_template("/users/{id", value).is_err());
+        assert!(expand_template("/users/id}", value).is_err());
+        assert!(expand_template("/users/{name}", value).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let value = |name: &str| (name == "status").then(|| "404".to_string());
+        assert_eq!(render("{\"code\": {{status}}}", value).unwrap(), "{\"code\": 404}");
+        assert!(render("{{status", value).is_err());
+        assert!(render("{{nope}}", value).is_err());
+    }
+}
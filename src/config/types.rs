@@ -1,65 +1,37 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use std::collections::HashMap;
 use crate::config::{loader, validator};
+use crate::config::secret::Secret;
+use crate::config::duration::{duration_serde, option_duration_serde, HumanDuration};
 
-mod duration_serde {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-    use std::time::Duration;
-
-    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        duration.as_secs().serialize(serializer)
-    }
-
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let secs = u64::deserialize(deserializer)?;
-        Ok(Duration::from_secs(secs))
-    }
-}
-
-mod option_duration_serde {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
-    use std::time::Duration;
-
-    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match duration {
-            Some(d) => d.as_secs().serialize(serializer),
-            None => serializer.serialize_none(),
-        }
-    }
-
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let secs = Option::<u64>::deserialize(deserializer)?;
-        Ok(secs.map(Duration::from_secs))
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     pub server: ServerConfig,
     pub logging: LoggingConfig,
     pub metrics: MetricsConfig,
     pub security: SecurityConfig,
     pub plugins: PluginsConfig,
+    /// Files or directories, relative to the config file, whose endpoints are
+    /// appended to `endpoints`. A directory contributes every JSON, YAML and
+    /// TOML file it contains in file name order, like a `conf.d/` directory.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
     pub endpoints: Vec<EndpointConfig>,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub forwarding: ForwardingConfig,
+    #[serde(default)]
+    pub retry_budget: RetryBudgetConfig,
     pub cluster: ClusterConfig,
     pub tls: TlsConfig,
     pub observability: ObservabilityConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
@@ -67,12 +39,104 @@ pub struct ServerConfig {
     pub ws_port: Option<u16>,
     pub workers: usize,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub timeout: Duration,
     #[serde(default = "default_max_request_size")]
     pub max_request_size: usize,
+    /// How long to keep accepting connections after a shutdown signal while
+    /// the health endpoint reports draining, so load balancers notice first.
+    #[serde(default, with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub drain_delay: Duration,
+    /// How long in-flight requests may take to finish once the server stops
+    /// accepting connections. Connections still open afterwards are closed.
+    #[serde(default = "default_shutdown_timeout", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub shutdown_timeout: Duration,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How request paths are normalized before they are matched against routes.
+///
+/// The defaults resolve the usual path-traversal tricks (`/a/../b`,
+/// `/a/%2e%2e/b`, `//a`) before matching while leaving encoded slashes and
+/// other reserved characters untouched.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct RoutingConfig {
+    pub trailing_slash: TrailingSlash,
+    /// Collapse runs of slashes, so `/a//b` matches `/a/b`.
+    pub merge_slashes: bool,
+    pub percent_decoding: PercentDecoding,
+    /// Resolve `.` and `..` segments, never climbing above the root.
+    pub remove_dot_segments: bool,
+    /// When false, static route segments match in any letter case.
+    pub case_sensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// `/users/` matches `/users`.
+    Strip,
+    /// `/users/` is redirected with a 308 to `/users` when that matches.
+    Redirect,
+    /// `/users/` only matches routes defined with a trailing slash.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PercentDecoding {
+    /// Match the path exactly as sent.
+    None,
+    /// Decode only unreserved characters (letters, digits, `-._~`).
+    Unreserved,
+    /// Decode everything except `/`, `\` and NUL, which stay encoded.
+    All,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            trailing_slash: TrailingSlash::Strip,
+            merge_slashes: true,
+            percent_decoding: PercentDecoding::Unreserved,
+            remove_dot_segments: true,
+            case_sensitive: true,
+        }
+    }
+}
+
+/// Headers the gateway adds to proxied requests so backends can see who the
+/// original client was.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ForwardingConfig {
+    /// Addresses or CIDR ranges of proxies in front of the gateway. Their
+    /// `X-Forwarded-*` and `Forwarded` headers are trusted when deriving the
+    /// client's address; anyone else's are replaced.
+    pub trusted_proxies: Vec<String>,
+    /// Send `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`.
+    pub x_forwarded: bool,
+    /// Send an RFC 7239 `Forwarded` header.
+    pub forwarded: bool,
+    /// Name the gateway adds to `Via` headers in both directions, or `null`
+    /// to leave them alone.
+    pub via: Option<String>,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            x_forwarded: true,
+            forwarded: false,
+            via: Some("rustopus".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LoggingConfig {
     pub level: String,
     pub format: String,
@@ -82,7 +146,7 @@ pub struct LoggingConfig {
     pub json_fields: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MetricsConfig {
     pub enabled: bool,
     pub port: u16,
@@ -92,7 +156,7 @@ pub struct MetricsConfig {
     pub tags: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SecurityConfig {
     #[serde(default)]
     pub cors: CorsConfig,
@@ -106,28 +170,33 @@ pub struct SecurityConfig {
     pub rbac: RbacConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct CorsConfig {
     pub enabled: bool,
+    #[serde(default)]
     pub allowed_origins: Vec<String>,
+    #[serde(default)]
     pub allowed_methods: Vec<String>,
+    #[serde(default)]
     pub allowed_headers: Vec<String>,
+    #[serde(default)]
     pub exposed_headers: Vec<String>,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub max_age: Duration,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct RateLimitConfig {
     pub enabled: bool,
     pub requests_per_second: u32,
     pub burst: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct AuthConfig {
     pub enabled: bool,
-    pub jwt_secret: Option<String>,
+    pub jwt_secret: Option<Secret>,
     pub jwt_issuer: Option<String>,
     pub jwt_audience: Option<String>,
     pub oauth: Option<OAuthConfig>,
@@ -136,37 +205,42 @@ pub struct AuthConfig {
     pub mfa: Option<MfaConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct WafConfig {
     pub enabled: bool,
     pub rules_file: Option<String>,
     pub block_mode: bool,
+    #[serde(default)]
     pub allowed_content_types: Vec<String>,
     pub max_request_size: usize,
     pub max_url_length: usize,
     pub max_header_count: usize,
     pub max_header_size: usize,
+    #[serde(default)]
     pub blocked_countries: Vec<String>,
+    #[serde(default)]
     pub blocked_ips: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct RbacConfig {
     pub enabled: bool,
     pub rules_file: Option<String>,
     pub default_role: String,
+    #[serde(default)]
     pub roles: HashMap<String, RoleConfig>,
+    #[serde(default)]
     pub policies: Vec<PolicyConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RoleConfig {
     pub name: String,
     pub permissions: Vec<String>,
     pub inherit_from: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PolicyConfig {
     pub name: String,
     pub effect: PolicyEffect,
@@ -175,38 +249,38 @@ pub struct PolicyConfig {
     pub conditions: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum PolicyEffect {
     Allow,
     Deny,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OAuthConfig {
     pub enabled: bool,
     pub providers: HashMap<String, OAuthProviderConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OAuthProviderConfig {
     pub client_id: String,
-    pub client_secret: String,
+    pub client_secret: Secret,
     pub authorize_url: String,
     pub token_url: String,
     pub scopes: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OidcConfig {
     pub enabled: bool,
     pub issuer_url: String,
     pub client_id: String,
-    pub client_secret: String,
+    pub client_secret: Secret,
     pub scopes: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ApiKeyConfig {
     pub enabled: bool,
     pub header_name: String,
@@ -214,14 +288,14 @@ pub struct ApiKeyConfig {
     pub query_param: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MfaConfig {
     pub enabled: bool,
     pub methods: Vec<MfaMethod>,
     pub enforcement: MfaEnforcement,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum MfaMethod {
     Totp,
@@ -230,7 +304,7 @@ pub enum MfaMethod {
     WebAuthn,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum MfaEnforcement {
     Always,
@@ -238,7 +312,7 @@ pub enum MfaEnforcement {
     Optional,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PluginsConfig {
     #[serde(default)]
     pub enabled: bool,
@@ -248,7 +322,7 @@ pub struct PluginsConfig {
     pub wasm_enabled: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum BackendProtocol {
     Rest,
@@ -256,7 +330,7 @@ pub enum BackendProtocol {
     WebSocket,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum GatewayProtocol {
     Rest,
@@ -275,16 +349,18 @@ impl std::str::FromStr for GatewayProtocol {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct EndpointConfig {
     pub path: String,
     pub method: String,
     pub backend: Vec<BackendConfig>,
     #[serde(default)]
     #[serde(with = "option_duration_serde")]
+    #[schemars(with = "Option<HumanDuration>")]
     pub timeout: Option<Duration>,
     #[serde(default)]
     #[serde(with = "option_duration_serde")]
+    #[schemars(with = "Option<HumanDuration>")]
     pub cache_ttl: Option<Duration>,
     #[serde(default)]
     pub rate_limit: Option<RateLimitConfig>,
@@ -294,61 +370,431 @@ pub struct EndpointConfig {
     pub protocol: GatewayProtocol,
     #[serde(default)]
     pub guards: Vec<String>,
+    /// Extra path prefixes this endpoint answers on, e.g. `/api/v1` for an
+    /// endpoint at `/api/users` so `/api/v1/users` reaches it too.
+    #[serde(default)]
+    pub prefix_rewrites: Vec<PrefixRewrite>,
+    /// Extra conditions a request must meet to use this endpoint, so the
+    /// same method and path can go to different backends per host, tenant
+    /// header and so on.
+    #[serde(default)]
+    pub matches: RequestMatchConfig,
+    /// How request and response bodies are handled on the way through.
+    /// Bodies, headers and status codes are relayed unchanged by default.
+    #[serde(default)]
+    pub transform: BodyTransform,
+    /// Bodies for the errors the gateway itself returns on this endpoint,
+    /// replacing the default `application/problem+json` body.
+    #[serde(default)]
+    pub error_templates: Vec<ErrorTemplate>,
+    /// How requests are spread across the endpoint's backends.
+    #[serde(default)]
+    pub load_balancing: LoadBalancing,
+    /// Take backends that misbehave in live traffic out of rotation.
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            method: String::new(),
+            backend: Vec::new(),
+            timeout: None,
+            cache_ttl: None,
+            rate_limit: None,
+            auth_required: false,
+            protocol: default_gateway_protocol(),
+            guards: Vec::new(),
+            prefix_rewrites: Vec::new(),
+            matches: RequestMatchConfig::default(),
+            transform: BodyTransform::default(),
+            error_templates: Vec::new(),
+            load_balancing: LoadBalancing::default(),
+            outlier_detection: None,
+        }
+    }
+}
+
+impl EndpointConfig {
+    /// Identifies the endpoint in logs, metrics and `/health`, e.g.
+    /// `GET /users/:id`.
+    pub fn name(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+}
+
+/// When backends are ejected from an endpoint's rotation based on the
+/// responses they give. Ejected backends come back by themselves, after a
+/// time that doubles each time they are ejected again.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OutlierDetectionConfig {
+    /// Failed requests and 5xx responses in a row that eject a backend.
+    #[serde(default = "default_consecutive_5xx")]
+    pub consecutive_5xx: u32,
+    #[serde(default)]
+    pub latency: Option<LatencyOutlierConfig>,
+    /// How long the first ejection lasts.
+    #[serde(default = "default_base_ejection_time", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub base_ejection_time: Duration,
+    /// Longest an ejection can last, up to 24h. A backend that has not been
+    /// ejected for this long starts again from `base_ejection_time`.
+    #[serde(default = "default_max_ejection_time", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub max_ejection_time: Duration,
+    /// Most backends, in percent of the endpoint's, that can be ejected at
+    /// the same time.
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+/// Ejects backends whose recent response times are too slow.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LatencyOutlierConfig {
+    /// Percentile of the latest response times compared with `threshold`.
+    #[serde(default = "default_latency_percentile")]
+    pub percentile: f64,
+    #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub threshold: Duration,
+    /// Responses needed before latency is judged, up to 10000.
+    #[serde(default = "default_latency_min_requests")]
+    pub min_requests: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancing {
+    /// Take turns in proportion to backend weights.
+    #[default]
+    RoundRobin,
+    /// Send to the backend with the fewest requests in flight for its weight.
+    LeastOutstanding,
+    /// Pick two backends at random and send to the less loaded one.
+    PowerOfTwoChoices,
+    /// Pick a backend at random in proportion to its weight.
+    Random,
+    /// Send requests with the same key to the same backend, for sticky
+    /// sessions. Requests without the key are balanced round-robin.
+    ConsistentHash(HashKey),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HashKey {
+    /// Value of the named request header.
+    Header(String),
+    /// Value of the named cookie.
+    Cookie(String),
+    /// The client's address, as used for `client_cidrs`.
+    ClientIp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorTemplate {
+    /// Status code the template is used for. A template without one is used
+    /// for every status that has no template of its own.
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default = "default_error_content_type")]
+    pub content_type: String,
+    /// Response body. `{{status}}`, `{{title}}`, `{{detail}}`, `{{type}}`,
+    /// `{{instance}}` and `{{request_id}}` are replaced with the error's
+    /// details.
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyTransform {
+    /// Proxy bodies byte for byte.
+    #[default]
+    None,
+    /// Require JSON bodies in both directions and pass the parsed values to
+    /// middleware. Invalid request bodies get a 400, invalid backend
+    /// responses a 502.
+    Json,
+}
+
+/// Conditions on a request besides its method and path. Every listed
+/// condition must hold; an empty list places no restriction.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RequestMatchConfig {
+    /// Accepted `Host` values, ignoring case and port. A leading `*.`
+    /// matches any subdomain, e.g. `*.example.com`.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub headers: Vec<ValueMatch>,
+    #[serde(default)]
+    pub query: Vec<ValueMatch>,
+    /// Client address ranges, e.g. `10.0.0.0/8`, or single addresses.
+    #[serde(default)]
+    pub client_cidrs: Vec<String>,
+}
+
+impl RequestMatchConfig {
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && self.headers.is_empty() && self.query.is_empty() && self.client_cidrs.is_empty()
+    }
+}
+
+/// A condition on a named header or query parameter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ValueMatch {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: ValueCondition,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueCondition {
+    /// The value equals this string.
+    Exact(String),
+    /// The value matches this regular expression anywhere.
+    Regex(String),
+    /// The value is present (`true`) or absent (`false`).
+    Present(bool),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Routes requests under `from` as if their path started with `to`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PrefixRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BackendConfig {
+    /// Backend URL. `{name}` placeholders are replaced with the path
+    /// parameters captured by the endpoint, e.g. `http://users:8080/users/{id}`.
     pub url: String,
+    /// Method to call the backend with instead of the client's.
     pub method: Option<String>,
+    /// Share of the endpoint's traffic the backend gets relative to the
+    /// others.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
     #[serde(with = "option_duration_serde")]
+    #[schemars(with = "Option<HumanDuration>")]
     pub timeout: Option<Duration>,
     #[serde(default)]
     pub circuit_breaker: Option<CircuitBreakerConfig>,
     #[serde(default)]
     pub retry: Option<RetryConfig>,
+    #[serde(default)]
+    pub health_check: Option<BackendHealthCheck>,
     #[serde(default = "default_backend_protocol")]
     pub protocol: BackendProtocol,
+    /// Which parameters of the incoming query string are appended to the
+    /// backend URL.
+    #[serde(default)]
+    pub forward_query: QueryForwarding,
+    /// Append the part of the request path matched by the endpoint's
+    /// trailing wildcard to the backend URL.
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Forward the request path with this prefix removed. Applies to the
+    /// wildcard suffix instead when `passthrough` is set.
+    #[serde(default)]
+    pub strip_prefix: Option<String>,
+    /// Forward the request path with this prefix added, after
+    /// `strip_prefix`. Applies to the wildcard suffix instead when
+    /// `passthrough` is set.
+    #[serde(default)]
+    pub add_prefix: Option<String>,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: None,
+            weight: default_weight(),
+            timeout: None,
+            circuit_breaker: None,
+            retry: None,
+            health_check: None,
+            protocol: default_backend_protocol(),
+            forward_query: QueryForwarding::default(),
+            passthrough: false,
+            strip_prefix: None,
+            add_prefix: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryForwarding {
+    /// Forward the whole query string unchanged.
+    #[default]
+    All,
+    /// Drop the query string.
+    None,
+    /// Forward only the listed parameters.
+    Only(Vec<String>),
+    /// Forward every parameter except the listed ones.
+    Except(Vec<String>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CircuitBreakerConfig {
-    pub threshold: u32,
+    /// Failure rate, in percent, at which the circuit opens. Connection
+    /// errors, timeouts and 5xx responses count as failures.
+    pub failure_rate_threshold: u32,
+    /// Length of the sliding window the failure rate is measured over.
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub window: Duration,
+    /// Requests needed in the window before the failure rate is acted on.
     pub min_requests: u32,
+    /// How long the circuit stays open before probe requests are let through.
+    #[serde(default = "default_open_duration", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub open_duration: Duration,
+    /// Probe requests let through while half-open. The circuit closes once
+    /// they all succeed and opens again on the first failure.
+    #[serde(default = "default_half_open_requests")]
+    pub half_open_requests: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How failed requests to a backend are retried.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RetryConfig {
+    /// Retries after the first attempt.
     pub attempts: u32,
+    /// Delay before the first retry. It doubles with every further retry up
+    /// to `max_backoff`, and a random part of it is waited.
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub backoff: Duration,
+    #[serde(default = "default_max_backoff", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub max_backoff: Duration,
+    /// Failures that are retried.
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryCondition>,
+    /// Response statuses that are retried as well.
+    #[serde(default)]
+    pub statuses: Vec<u16>,
+    /// Also retry methods that are not idempotent, such as POST and PATCH,
+    /// after the backend may have received them. Connection failures are
+    /// retried for every method since the request never got through.
+    #[serde(default)]
+    pub non_idempotent: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryCondition {
+    /// The backend could not be connected to.
+    ConnectError,
+    /// The backend did not answer within its `timeout`.
+    Timeout,
+    /// The backend answered 502, 503 or 504.
+    GatewayError,
+}
+
+/// Background checks that take a backend out of rotation while it fails
+/// them.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BackendHealthCheck {
+    #[serde(default)]
+    pub protocol: HealthCheckProtocol,
+    /// Path requested by HTTP checks. A 2xx or 3xx answer passes.
+    #[serde(default = "default_health_check_path")]
+    pub path: String,
+    /// Time between checks. Each wait is varied by up to 10% so checks of
+    /// different backends spread out.
+    #[serde(default = "default_health_check_interval", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub interval: Duration,
+    #[serde(default = "default_health_check_timeout", with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub timeout: Duration,
+    /// Checks in a row that must pass before an unhealthy backend is used
+    /// again.
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+    /// Checks in a row that must fail before a backend is taken out of
+    /// rotation.
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckProtocol {
+    /// Request `path` and expect a 2xx or 3xx status.
+    #[default]
+    Http,
+    /// Only open a TCP connection to the backend's host and port.
+    Tcp,
+}
+
+/// Caps retries across all backends, so a struggling backend is not
+/// swamped by retries on top of its regular traffic.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct RetryBudgetConfig {
+    /// Retries allowed per request: 0.2 allows one retry for every five
+    /// requests.
+    pub ratio: f32,
+    /// Retries allowed per second on top of `ratio`, so endpoints with
+    /// little traffic can still retry.
+    pub min_per_second: u32,
+    /// How long a request counts towards the budget, from 1s to 60s.
+    #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
+    pub window: Duration,
+}
+
+impl Default for RetryBudgetConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 0.2,
+            min_per_second: 10,
+            window: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ClusterConfig {
     #[serde(default)]
     pub enabled: bool,
     pub discovery_method: Option<String>,
+    #[serde(default)]
     pub discovery_endpoints: Vec<String>,
     pub node_name: Option<String>,
     pub node_role: Option<String>,
+    #[serde(default)]
+    #[serde(with = "option_duration_serde")]
+    #[schemars(with = "Option<HumanDuration>")]
     pub sync_interval: Option<Duration>,
     pub leader_election: Option<LeaderElectionConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LeaderElectionConfig {
     pub enabled: bool,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub lease_duration: Duration,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub renew_deadline: Duration,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub retry_period: Duration,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TlsConfig {
     pub enabled: bool,
     pub cert_file: Option<String>,
@@ -356,11 +802,13 @@ pub struct TlsConfig {
     pub ca_file: Option<String>,
     pub verify_client: bool,
     pub min_version: Option<String>,
+    #[serde(default)]
     pub cipher_suites: Vec<String>,
+    #[serde(default)]
     pub alpn_protocols: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ObservabilityConfig {
     pub tracing: TracingConfig,
     pub metrics: MetricsConfig,
@@ -368,18 +816,20 @@ pub struct ObservabilityConfig {
     pub health: HealthConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TracingConfig {
     pub enabled: bool,
     pub provider: TracingProvider,
     pub sampling_ratio: f64,
     pub service_name: String,
     pub environment: String,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
+    #[serde(default)]
     pub exporters: Vec<TracingExporter>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TracingProvider {
     OpenTelemetry,
@@ -388,35 +838,43 @@ pub enum TracingProvider {
     DataDog,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TracingExporter {
     pub name: String,
     pub endpoint: String,
     pub protocol: String,
-    pub headers: HashMap<String, String>,
+    pub headers: HashMap<String, Secret>,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub timeout: Duration,
     pub batch_size: usize,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub flush_interval: Duration,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct HealthConfig {
     pub enabled: bool,
     pub path: String,
     pub include_details: bool,
+    #[serde(default)]
     pub checks: Vec<HealthCheck>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct HealthCheck {
     pub name: String,
     pub enabled: bool,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub timeout: Duration,
     #[serde(with = "duration_serde")]
+    #[schemars(with = "HumanDuration")]
     pub interval: Duration,
+    #[serde(default)]
+    #[serde(with = "option_duration_serde")]
+    #[schemars(with = "Option<HumanDuration>")]
     pub initial_delay: Option<Duration>,
     pub required: bool,
 }
@@ -425,10 +883,82 @@ fn default_timeout() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_open_duration() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_half_open_requests() -> u32 {
+    1
+}
+
+fn default_consecutive_5xx() -> u32 {
+    5
+}
+
+fn default_base_ejection_time() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_max_ejection_time() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+
+fn default_latency_percentile() -> f64 {
+    99.0
+}
+
+fn default_latency_min_requests() -> u32 {
+    20
+}
+
+fn default_health_check_path() -> String {
+    "/health".to_string()
+}
+
+fn default_health_check_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_health_check_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_retry_on() -> Vec<RetryCondition> {
+    vec![RetryCondition::ConnectError, RetryCondition::GatewayError]
+}
+
+fn default_error_content_type() -> String {
+    "application/problem+json".to_string()
+}
+
 fn default_max_request_size() -> usize {
     1024 * 1024 * 10 // 10MB
 }
 
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
 fn default_gateway_protocol() -> GatewayProtocol {
     GatewayProtocol::Rest
 }
@@ -439,15 +969,26 @@ fn default_backend_protocol() -> BackendProtocol {
 
 impl Config {
     pub fn load() -> anyhow::Result<Self> {
-        let config_path = std::env::var("GATEWAY_CONFIG")
-            .unwrap_or_else(|_| "gateway-config.json".to_string());
+        Self::load_from(&loader::ConfigSource::new(Self::path()))
+    }
 
-        let config = loader::load_config(&std::path::PathBuf::from(config_path))?;
+    /// Loads the layered configuration described by `source` and validates it.
+    pub fn load_from(source: &loader::ConfigSource) -> anyhow::Result<Self> {
+        let config = source.load()?;
         validator::validate_config(&config)?;
         Ok(config)
     }
 
-    pub fn default() -> Self {
+    /// Location of the configuration file, taken from `GATEWAY_CONFIG`.
+    pub fn path() -> std::path::PathBuf {
+        std::env::var("GATEWAY_CONFIG")
+            .unwrap_or_else(|_| "gateway-config.json".to_string())
+            .into()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
         Self {
             server: ServerConfig {
                 host: "127.0.0.1".to_string(),
@@ -456,6 +997,8 @@ impl Config {
                 workers: num_cpus::get(),
                 timeout: default_timeout(),
                 max_request_size: default_max_request_size(),
+                drain_delay: Duration::ZERO,
+                shutdown_timeout: default_shutdown_timeout(),
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
@@ -481,7 +1024,11 @@ impl Config {
                 directory: None,
                 wasm_enabled: false,
             },
+            include: vec![],
             endpoints: vec![],
+            routing: RoutingConfig::default(),
+            forwarding: ForwardingConfig::default(),
+            retry_budget: RetryBudgetConfig::default(),
             cluster: ClusterConfig {
                 enabled: false,
                 discovery_method: None,
@@ -524,7 +1071,7 @@ impl Config {
                     json_fields: vec![],
                 },
                 health: HealthConfig {
-                    enabled: false,
+                    enabled: true,
                     path: "/health".to_string(),
                     include_details: false,
                     checks: vec![],
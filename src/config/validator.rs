@@ -1,158 +1,962 @@
-use super::Config;
-use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
 use std::time::Duration;
+use reqwest::Url;
+use super::{Config, Secret};
+use super::template::{expand_template, render, TEMPLATE_FIELDS, WILDCARD_PARAM};
+use super::types::{
+    BackendProtocol, ClusterConfig, EndpointConfig, ForwardingConfig, GatewayProtocol, HashKey, HealthCheckProtocol, LoadBalancing, LoggingConfig,
+    MetricsConfig, ObservabilityConfig, PluginsConfig, RateLimitConfig, RbacConfig, RequestMatchConfig, RetryBudgetConfig,
+    SecurityConfig, ServerConfig, TlsConfig, ValueCondition, WafConfig,
+};
 
-pub fn validate_config(config: &Config) -> Result<()> {
-    validate_server_config(&config.server)?;
-    validate_logging_config(&config.logging)?;
-    validate_metrics_config(&config.metrics)?;
-    validate_security_config(&config.security)?;
-    validate_plugins_config(&config.plugins)?;
-    validate_endpoints_config(&config.endpoints)?;
-    Ok(())
+/// Longest outlier ejection allowed. Ejections are timed with `Instant`
+/// arithmetic that overflows on durations far beyond any useful one.
+const MAX_EJECTION_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A single problem found in the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Location of the offending value, e.g. `endpoints[3].backend[0].url`.
+    pub path: String,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, " (hint: {})", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every problem found in a configuration, in the order they were detected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    fn add(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.push(path, message, None::<String>);
+    }
+
+    fn add_with_hint(&mut self, path: impl Into<String>, message: impl Into<String>, hint: impl Into<String>) {
+        self.push(path, message, Some(hint));
+    }
+
+    fn push(&mut self, path: impl Into<String>, message: impl Into<String>, hint: Option<impl Into<String>>) {
+        self.errors.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+            hint: hint.map(Into::into),
+        });
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let noun = if self.errors.len() == 1 { "error" } else { "errors" };
+        write!(f, "Invalid configuration ({} {})", self.errors.len(), noun)?;
+        for error in &self.errors {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
 }
 
-fn validate_server_config(config: &super::types::ServerConfig) -> Result<()> {
+impl std::error::Error for ValidationErrors {}
+
+/// Checks the whole configuration and reports every problem at once.
+pub fn validate_config(config: &Config) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    validate_server_config(&mut errors, "server", &config.server);
+    validate_logging_config(&mut errors, "logging", &config.logging);
+    validate_metrics_config(&mut errors, "metrics", &config.metrics);
+    if config.metrics.enabled && config.metrics.port == config.server.port {
+        errors.add("metrics.port", "Metrics port must differ from server.port");
+    }
+    validate_security_config(&mut errors, "security", &config.security);
+    validate_plugins_config(&mut errors, "plugins", &config.plugins);
+    validate_endpoints_config(&mut errors, "endpoints", &config.endpoints, config.security.auth.enabled);
+    validate_forwarding_config(&mut errors, "forwarding", &config.forwarding);
+    validate_retry_budget_config(&mut errors, "retry_budget", &config.retry_budget);
+    validate_cluster_config(&mut errors, "cluster", &config.cluster);
+    validate_tls_config(&mut errors, "tls", &config.tls);
+    validate_observability_config(&mut errors, "observability", &config.observability);
+    let health = &config.observability.health;
+    for (i, endpoint) in config.endpoints.iter().enumerate() {
+        if health.enabled && endpoint.path == health.path {
+            errors.add(format!("endpoints[{}].path", i), "Path is taken by the health check");
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate_server_config(errors: &mut ValidationErrors, path: &str, config: &ServerConfig) {
     if config.port == 0 {
-        return Err(anyhow::anyhow!("Server port cannot be 0"));
+        errors.add(format!("{}.port", path), "Server port cannot be 0");
     }
 
     if config.workers == 0 {
-        return Err(anyhow::anyhow!("Number of workers cannot be 0"));
+        errors.add_with_hint(
+            format!("{}.workers", path),
+            "Number of workers cannot be 0",
+            "omit the field to use one worker per CPU",
+        );
     }
 
     if config.timeout < Duration::from_secs(1) {
-        return Err(anyhow::anyhow!("Server timeout must be at least 1 second"));
+        errors.add_with_hint(
+            format!("{}.timeout", path),
+            "Server timeout must be at least 1 second",
+            "use a value such as \"30s\"",
+        );
     }
 
     if config.max_request_size == 0 {
-        return Err(anyhow::anyhow!("Max request size cannot be 0"));
+        errors.add(format!("{}.max_request_size", path), "Max request size cannot be 0");
     }
 
-    Ok(())
+    if config.shutdown_timeout.is_zero() {
+        errors.add_with_hint(
+            format!("{}.shutdown_timeout", path),
+            "Shutdown timeout cannot be 0",
+            "in-flight requests would be cut off on shutdown; use a value such as \"30s\"",
+        );
+    }
 }
 
-fn validate_logging_config(config: &super::types::LoggingConfig) -> Result<()> {
+fn validate_logging_config(errors: &mut ValidationErrors, path: &str, config: &LoggingConfig) {
     match config.level.to_lowercase().as_str() {
         "trace" | "debug" | "info" | "warn" | "error" => {}
-        _ => return Err(anyhow::anyhow!("Invalid log level")),
+        _ => errors.add_with_hint(
+            format!("{}.level", path),
+            format!("Invalid log level \"{}\"", config.level),
+            "expected one of trace, debug, info, warn, error",
+        ),
     }
 
     match config.format.to_lowercase().as_str() {
         "json" | "text" => {}
-        _ => return Err(anyhow::anyhow!("Invalid log format")),
+        _ => errors.add_with_hint(
+            format!("{}.format", path),
+            format!("Invalid log format \"{}\"", config.format),
+            "expected json or text",
+        ),
     }
 
-    if let Some(path) = &config.file_output {
-        if path.is_empty() {
-            return Err(anyhow::anyhow!("Log file path cannot be empty"));
+    if let Some(file) = &config.file_output {
+        if file.is_empty() {
+            errors.add_with_hint(
+                format!("{}.file_output", path),
+                "Log file path cannot be empty",
+                "remove the field to log to stdout",
+            );
         }
     }
-
-    Ok(())
 }
 
-fn validate_metrics_config(config: &super::types::MetricsConfig) -> Result<()> {
+fn validate_metrics_config(errors: &mut ValidationErrors, path: &str, config: &MetricsConfig) {
     if config.enabled && config.port == 0 {
-        return Err(anyhow::anyhow!("Metrics port cannot be 0 when metrics are enabled"));
+        errors.add(format!("{}.port", path), "Metrics port cannot be 0 when metrics are enabled");
     }
 
     if config.path.is_empty() {
-        return Err(anyhow::anyhow!("Metrics path cannot be empty"));
+        errors.add_with_hint(format!("{}.path", path), "Metrics path cannot be empty", "use \"/metrics\"");
+    } else if !config.path.starts_with('/') {
+        errors.add(format!("{}.path", path), "Metrics path must start with '/'");
     }
+}
 
-    if !config.path.starts_with('/') {
-        return Err(anyhow::anyhow!("Metrics path must start with '/'"));
+fn validate_rate_limit(errors: &mut ValidationErrors, path: &str, config: &RateLimitConfig) {
+    if config.enabled && config.requests_per_second == 0 {
+        errors.add(format!("{}.requests_per_second", path), "Rate limit requests per second cannot be 0");
     }
-
-    Ok(())
 }
 
-fn validate_security_config(config: &super::types::SecurityConfig) -> Result<()> {
+fn validate_security_config(errors: &mut ValidationErrors, path: &str, config: &SecurityConfig) {
     if config.cors.enabled {
         if config.cors.allowed_origins.is_empty() {
-            return Err(anyhow::anyhow!("CORS allowed origins cannot be empty when CORS is enabled"));
+            errors.add_with_hint(
+                format!("{}.cors.allowed_origins", path),
+                "CORS allowed origins cannot be empty when CORS is enabled",
+                "use [\"*\"] to allow any origin",
+            );
         }
         if config.cors.allowed_methods.is_empty() {
-            return Err(anyhow::anyhow!("CORS allowed methods cannot be empty when CORS is enabled"));
+            errors.add(
+                format!("{}.cors.allowed_methods", path),
+                "CORS allowed methods cannot be empty when CORS is enabled",
+            );
+        }
+        for (i, method) in config.cors.allowed_methods.iter().enumerate() {
+            if http::Method::from_bytes(method.as_bytes()).is_err() {
+                errors.add(
+                    format!("{}.cors.allowed_methods[{}]", path, i),
+                    format!("Invalid HTTP method \"{}\"", method),
+                );
+            }
         }
     }
 
-    if config.rate_limit.enabled {
-        if config.rate_limit.requests_per_second == 0 {
-            return Err(anyhow::anyhow!("Rate limit requests per second cannot be 0"));
+    validate_rate_limit(errors, &format!("{}.rate_limit", path), &config.rate_limit);
+
+    if config.auth.enabled && config.auth.jwt_secret.as_ref().is_none_or(Secret::is_empty) {
+        errors.add_with_hint(
+            format!("{}.auth.jwt_secret", path),
+            "JWT secret must be provided when auth is enabled",
+            "set security.auth.jwt_secret, e.g. to ${env:JWT_SECRET}, or disable auth",
+        );
+    }
+
+    validate_waf_config(errors, &format!("{}.waf", path), &config.waf);
+    validate_rbac_config(errors, &format!("{}.rbac", path), &config.rbac);
+}
+
+fn validate_waf_config(errors: &mut ValidationErrors, path: &str, config: &WafConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    if let Some(rules_file) = &config.rules_file {
+        if rules_file.is_empty() {
+            errors.add(format!("{}.rules_file", path), "WAF rules file path cannot be empty");
         }
     }
 
-    if config.auth.enabled {
-        if config.auth.jwt_secret.is_none() {
-            return Err(anyhow::anyhow!("JWT secret must be provided when auth is enabled"));
+    for (i, content_type) in config.allowed_content_types.iter().enumerate() {
+        if content_type.parse::<http::HeaderValue>().is_err() || !content_type.contains('/') {
+            errors.add_with_hint(
+                format!("{}.allowed_content_types[{}]", path, i),
+                format!("Invalid content type \"{}\"", content_type),
+                "expected a media type such as application/json",
+            );
         }
     }
 
-    Ok(())
+    for (i, country) in config.blocked_countries.iter().enumerate() {
+        if country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase()) {
+            errors.add_with_hint(
+                format!("{}.blocked_countries[{}]", path, i),
+                format!("Invalid country code \"{}\"", country),
+                "use ISO 3166-1 alpha-2 codes such as \"DE\"",
+            );
+        }
+    }
+
+    for (i, ip) in config.blocked_ips.iter().enumerate() {
+        if !is_ip_or_cidr(ip) {
+            errors.add_with_hint(
+                format!("{}.blocked_ips[{}]", path, i),
+                format!("Invalid IP address or CIDR range \"{}\"", ip),
+                "expected an address such as 10.0.0.1 or a range such as 10.0.0.0/8",
+            );
+        }
+    }
 }
 
-fn validate_plugins_config(config: &super::types::PluginsConfig) -> Result<()> {
-    if config.enabled {
-        if config.directory.is_none() {
-            return Err(anyhow::anyhow!("Plugin directory must be specified when plugins are enabled"));
+fn validate_rbac_config(errors: &mut ValidationErrors, path: &str, config: &RbacConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    if config.default_role.is_empty() {
+        errors.add(format!("{}.default_role", path), "Default role cannot be empty when RBAC is enabled");
+    } else if !config.roles.contains_key(&config.default_role) && config.rules_file.is_none() {
+        errors.add_with_hint(
+            format!("{}.default_role", path),
+            format!("Default role \"{}\" is not defined", config.default_role),
+            "add it under security.rbac.roles",
+        );
+    }
+
+    let mut role_names: Vec<_> = config.roles.keys().collect();
+    role_names.sort();
+    for name in role_names {
+        let role = &config.roles[name];
+        for (i, parent) in role.inherit_from.iter().enumerate() {
+            if !config.roles.contains_key(parent) {
+                errors.add(
+                    format!("{}.roles.{}.inherit_from[{}]", path, name, i),
+                    format!("Role \"{}\" inherits from undefined role \"{}\"", name, parent),
+                );
+            }
+        }
+        if inherits_from_itself(name, &config.roles) {
+            errors.add_with_hint(
+                format!("{}.roles.{}.inherit_from", path, name),
+                format!("Role \"{}\" is part of an inheritance cycle", name),
+                "remove one of the inherit_from links",
+            );
+        }
+    }
+
+    for (i, policy) in config.policies.iter().enumerate() {
+        let policy_path = format!("{}.policies[{}]", path, i);
+        if policy.name.is_empty() {
+            errors.add(format!("{}.name", policy_path), "Policy name cannot be empty");
+        }
+        if policy.actions.is_empty() {
+            errors.add(format!("{}.actions", policy_path), "Policy must list at least one action");
+        }
+        if policy.resources.is_empty() {
+            errors.add_with_hint(
+                format!("{}.resources", policy_path),
+                "Policy must list at least one resource",
+                "use [\"*\"] to match every resource",
+            );
+        }
+    }
+}
+
+fn inherits_from_itself(role: &str, roles: &HashMap<String, super::types::RoleConfig>) -> bool {
+    let mut stack: Vec<&str> = vec![role];
+    let mut seen = HashSet::new();
+    while let Some(current) = stack.pop() {
+        let Some(config) = roles.get(current) else { continue };
+        for parent in &config.inherit_from {
+            if parent == role {
+                return true;
+            }
+            if seen.insert(parent.as_str()) {
+                stack.push(parent);
+            }
         }
     }
-    Ok(())
+    false
 }
 
-fn validate_endpoints_config(endpoints: &[super::types::EndpointConfig]) -> Result<()> {
-    for endpoint in endpoints {
+fn validate_plugins_config(errors: &mut ValidationErrors, path: &str, config: &PluginsConfig) {
+    if config.enabled && config.directory.is_none() {
+        errors.add(
+            format!("{}.directory", path),
+            "Plugin directory must be specified when plugins are enabled",
+        );
+    }
+}
+
+fn validate_endpoints_config(errors: &mut ValidationErrors, path: &str, endpoints: &[EndpointConfig], auth_enabled: bool) {
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        let endpoint_path = format!("{}[{}]", path, i);
+
         if endpoint.path.is_empty() {
-            return Err(anyhow::anyhow!("Endpoint path cannot be empty"));
+            errors.add(format!("{}.path", endpoint_path), "Endpoint path cannot be empty");
+        } else if !endpoint.path.starts_with('/') {
+            errors.add_with_hint(
+                format!("{}.path", endpoint_path),
+                "Endpoint path must start with '/'",
+                format!("did you mean \"/{}\"?", endpoint.path),
+            );
+        }
+
+        if http::Method::from_bytes(endpoint.method.as_bytes()).is_err() {
+            errors.add(
+                format!("{}.method", endpoint_path),
+                format!("Invalid HTTP method \"{}\"", endpoint.method),
+            );
         }
 
-        if !endpoint.path.starts_with('/') {
-            return Err(anyhow::anyhow!("Endpoint path must start with '/'"));
+        for (j, rewrite) in endpoint.prefix_rewrites.iter().enumerate() {
+            let rewrite_path = format!("{}.prefix_rewrites[{}]", endpoint_path, j);
+            for (field, prefix) in [("from", &rewrite.from), ("to", &rewrite.to)] {
+                if !prefix.starts_with('/') {
+                    errors.add(format!("{}.{}", rewrite_path, field), "Path prefix must start with '/'");
+                }
+            }
+            if !has_path_prefix(&endpoint.path, &rewrite.to) {
+                errors.add_with_hint(
+                    format!("{}.to", rewrite_path),
+                    format!("Endpoint path \"{}\" does not start with \"{}\"", endpoint.path, rewrite.to),
+                    "`to` must be a prefix of the endpoint path, ending at a segment boundary",
+                );
+            }
+        }
+
+        validate_request_match(errors, &format!("{}.matches", endpoint_path), &endpoint.matches);
+
+        if let Some(rate_limit) = &endpoint.rate_limit {
+            validate_rate_limit(errors, &format!("{}.rate_limit", endpoint_path), rate_limit);
         }
 
         if endpoint.backend.is_empty() {
-            return Err(anyhow::anyhow!("Endpoint must have at least one backend"));
+            errors.add(format!("{}.backend", endpoint_path), "Endpoint must have at least one backend");
+        }
+
+        match &endpoint.load_balancing {
+            LoadBalancing::ConsistentHash(HashKey::Header(name)) if http::HeaderName::from_bytes(name.as_bytes()).is_err() => {
+                errors.add(
+                    format!("{}.load_balancing.consistent_hash.header", endpoint_path),
+                    format!("Invalid header name \"{}\"", name),
+                );
+            }
+            LoadBalancing::ConsistentHash(HashKey::Cookie(name)) if name.is_empty() || name.contains([';', '=', ' ']) => {
+                errors.add(
+                    format!("{}.load_balancing.consistent_hash.cookie", endpoint_path),
+                    format!("Invalid cookie name \"{}\"", name),
+                );
+            }
+            _ => {}
+        }
+
+        if let Some(outlier) = &endpoint.outlier_detection {
+            let outlier_path = format!("{}.outlier_detection", endpoint_path);
+            if outlier.consecutive_5xx == 0 {
+                errors.add(format!("{}.consecutive_5xx", outlier_path), "Consecutive 5xx threshold cannot be 0");
+            }
+            if outlier.base_ejection_time.is_zero() {
+                errors.add(format!("{}.base_ejection_time", outlier_path), "Ejection time cannot be 0");
+            }
+            if outlier.max_ejection_time < outlier.base_ejection_time {
+                errors.add(
+                    format!("{}.max_ejection_time", outlier_path),
+                    "Maximum ejection time cannot be shorter than the base ejection time",
+                );
+            } else if outlier.max_ejection_time > MAX_EJECTION_TIME {
+                errors.add(format!("{}.max_ejection_time", outlier_path), "Maximum ejection time cannot exceed 24h");
+            }
+            if !(1..=100).contains(&outlier.max_ejection_percent) {
+                errors.add(
+                    format!("{}.max_ejection_percent", outlier_path),
+                    "Maximum ejection percentage must be between 1 and 100",
+                );
+            }
+            if let Some(latency) = &outlier.latency {
+                if !(latency.percentile > 0.0 && latency.percentile <= 100.0) {
+                    errors.add(
+                        format!("{}.latency.percentile", outlier_path),
+                        "Latency percentile must be above 0 and at most 100",
+                    );
+                }
+                if !(1..=10_000).contains(&latency.min_requests) {
+                    errors.add(
+                        format!("{}.latency.min_requests", outlier_path),
+                        "Latency minimum requests must be between 1 and 10000",
+                    );
+                }
+            }
         }
 
-        // Validate protocol compatibility
-        for backend in &endpoint.backend {
+        for (j, backend) in endpoint.backend.iter().enumerate() {
+            let backend_path = format!("{}.backend[{}]", endpoint_path, j);
+
+            if !(1..=1000).contains(&backend.weight) {
+                errors.add(format!("{}.weight", backend_path), "Backend weight must be between 1 and 1000");
+            }
+
+            // Validate protocol compatibility
             match (&endpoint.protocol, &backend.protocol) {
-                (super::types::GatewayProtocol::Rest, super::types::BackendProtocol::WebSocket) => {
-                    return Err(anyhow::anyhow!("REST gateway cannot proxy to WebSocket backend"));
+                (GatewayProtocol::Rest, BackendProtocol::WebSocket) => {
+                    errors.add(format!("{}.protocol", backend_path), "REST gateway cannot proxy to WebSocket backend");
                 }
-                (super::types::GatewayProtocol::WebSocket, super::types::BackendProtocol::Rest) => {
-                    return Err(anyhow::anyhow!("WebSocket gateway cannot proxy to REST backend"));
+                (GatewayProtocol::WebSocket, BackendProtocol::Rest) => {
+                    errors.add(format!("{}.protocol", backend_path), "WebSocket gateway cannot proxy to REST backend");
                 }
                 _ => {}
             }
 
-            if backend.url.is_empty() {
-                return Err(anyhow::anyhow!("Backend URL cannot be empty"));
+            let schemes: &[&str] = match backend.protocol {
+                BackendProtocol::WebSocket => &["ws", "wss"],
+                BackendProtocol::Rest | BackendProtocol::Grpc => &["http", "https"],
+            };
+            // Check the URL with its placeholders filled in, so `{id}` in the
+            // path is accepted while unknown parameters are reported
+            let params = route_params(&endpoint.path);
+            match expand_template(&backend.url, |name| params.contains(&name).then(|| "x".to_string())) {
+                Ok(url) => validate_url(errors, &format!("{}.url", backend_path), "Backend URL", &url, schemes),
+                Err(e) => errors.add_with_hint(
+                    format!("{}.url", backend_path),
+                    e.to_string(),
+                    format!("placeholders can use the endpoint's path parameters: {}", params.join(", ")),
+                ),
+            }
+
+            if backend.passthrough && !params.contains(&WILDCARD_PARAM) {
+                errors.add_with_hint(
+                    format!("{}.passthrough", backend_path),
+                    "Passthrough requires the endpoint path to end with a wildcard",
+                    format!("e.g. \"{}/*\"", endpoint.path.trim_end_matches('/')),
+                );
+            }
+            for (field, prefix) in [("strip_prefix", &backend.strip_prefix), ("add_prefix", &backend.add_prefix)] {
+                if prefix.as_ref().is_some_and(|prefix| !prefix.starts_with('/')) {
+                    errors.add(format!("{}.{}", backend_path, field), "Path prefix must start with '/'");
+                }
+            }
+
+            if let Some(method) = &backend.method {
+                if http::Method::from_bytes(method.as_bytes()).is_err() {
+                    errors.add(format!("{}.method", backend_path), format!("Invalid HTTP method \"{}\"", method));
+                }
             }
 
             if let Some(circuit_breaker) = &backend.circuit_breaker {
-                if circuit_breaker.threshold == 0 {
-                    return Err(anyhow::anyhow!("Circuit breaker threshold cannot be 0"));
+                if circuit_breaker.failure_rate_threshold == 0 || circuit_breaker.failure_rate_threshold > 100 {
+                    errors.add_with_hint(
+                        format!("{}.circuit_breaker.failure_rate_threshold", backend_path),
+                        "Circuit breaker failure rate threshold must be between 1 and 100",
+                        "the failure rate threshold is in percent",
+                    );
+                }
+                if circuit_breaker.window.is_zero() {
+                    errors.add(format!("{}.circuit_breaker.window", backend_path), "Circuit breaker window cannot be 0");
+                }
+                if circuit_breaker.open_duration.is_zero() {
+                    errors.add(format!("{}.circuit_breaker.open_duration", backend_path), "Circuit breaker open duration cannot be 0");
+                }
+                if circuit_breaker.half_open_requests == 0 {
+                    errors.add(
+                        format!("{}.circuit_breaker.half_open_requests", backend_path),
+                        "Circuit breaker half-open requests cannot be 0",
+                    );
                 }
                 if circuit_breaker.min_requests == 0 {
-                    return Err(anyhow::anyhow!("Circuit breaker minimum requests cannot be 0"));
+                    errors.add(
+                        format!("{}.circuit_breaker.min_requests", backend_path),
+                        "Circuit breaker minimum requests cannot be 0",
+                    );
+                }
+            }
+
+            if let Some(health_check) = &backend.health_check {
+                let check_path = format!("{}.health_check", backend_path);
+                if health_check.protocol == HealthCheckProtocol::Http && !health_check.path.starts_with('/') {
+                    errors.add(format!("{}.path", check_path), "Health check path must start with '/'");
+                }
+                if health_check.interval.is_zero() {
+                    errors.add(format!("{}.interval", check_path), "Health check interval cannot be 0");
+                }
+                if health_check.timeout.is_zero() {
+                    errors.add(format!("{}.timeout", check_path), "Health check timeout cannot be 0");
+                }
+                for (field, threshold) in [
+                    ("healthy_threshold", health_check.healthy_threshold),
+                    ("unhealthy_threshold", health_check.unhealthy_threshold),
+                ] {
+                    if threshold == 0 {
+                        errors.add(format!("{}.{}", check_path, field), "Health check threshold cannot be 0");
+                    }
                 }
             }
 
             if let Some(retry) = &backend.retry {
                 if retry.attempts == 0 {
-                    return Err(anyhow::anyhow!("Retry attempts cannot be 0"));
+                    errors.add_with_hint(
+                        format!("{}.retry.attempts", backend_path),
+                        "Retry attempts cannot be 0",
+                        "remove the retry section to disable retries",
+                    );
+                }
+                if retry.max_backoff < retry.backoff {
+                    errors.add(
+                        format!("{}.retry.max_backoff", backend_path),
+                        "Maximum retry backoff cannot be shorter than the backoff",
+                    );
+                }
+                for (j, status) in retry.statuses.iter().enumerate() {
+                    if !(100..=599).contains(status) {
+                        errors.add(
+                            format!("{}.retry.statuses[{}]", backend_path, j),
+                            format!("Invalid HTTP status {}", status),
+                        );
+                    }
                 }
             }
         }
 
+        for (i, template) in endpoint.error_templates.iter().enumerate() {
+            let template_path = format!("{}.error_templates[{}]", endpoint_path, i);
+            if let Some(status) = template.status.filter(|status| !(400..=599).contains(status)) {
+                errors.add(format!("{}.status", template_path), format!("Error templates need a 4xx or 5xx status, got {}", status));
+            }
+            if http::HeaderValue::from_str(&template.content_type).is_err() {
+                errors.add(format!("{}.content_type", template_path), format!("Invalid content type \"{}\"", template.content_type));
+            }
+            let known = |name: &str| TEMPLATE_FIELDS.contains(&name).then(String::new);
+            if let Err(e) = render(&template.body, known) {
+                errors.add_with_hint(
+                    format!("{}.body", template_path),
+                    e.to_string(),
+                    format!("available fields are {}", TEMPLATE_FIELDS.join(", ")),
+                );
+            }
+        }
+
         // Validate guards if auth is required
         if endpoint.auth_required && endpoint.guards.is_empty() {
-            return Err(anyhow::anyhow!("Auth required but no guards specified"));
+            errors.add(format!("{}.guards", endpoint_path), "Auth required but no guards specified");
+        }
+        if endpoint.auth_required && !auth_enabled {
+            errors.add_with_hint(
+                format!("{}.auth_required", endpoint_path),
+                "Auth required but security.auth is disabled, so every request would be rejected",
+                "enable security.auth with a jwt_secret",
+            );
+        }
+    }
+}
+
+fn validate_cluster_config(errors: &mut ValidationErrors, path: &str, config: &ClusterConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    match config.discovery_method.as_deref() {
+        None | Some("") => errors.add_with_hint(
+            format!("{}.discovery_method", path),
+            "Discovery method must be specified when clustering is enabled",
+            "expected one of static, dns, kubernetes, consul",
+        ),
+        Some("static") | Some("dns") | Some("kubernetes") | Some("consul") => {}
+        Some(other) => errors.add_with_hint(
+            format!("{}.discovery_method", path),
+            format!("Unknown discovery method \"{}\"", other),
+            "expected one of static, dns, kubernetes, consul",
+        ),
+    }
+
+    if config.discovery_method.as_deref() == Some("static") && config.discovery_endpoints.is_empty() {
+        errors.add(
+            format!("{}.discovery_endpoints", path),
+            "Static discovery requires at least one discovery endpoint",
+        );
+    }
+
+    if config.sync_interval == Some(Duration::ZERO) {
+        errors.add(format!("{}.sync_interval", path), "Sync interval cannot be 0");
+    }
+
+    if let Some(election) = config.leader_election.as_ref().filter(|e| e.enabled) {
+        if election.renew_deadline >= election.lease_duration {
+            errors.add_with_hint(
+                format!("{}.leader_election.renew_deadline", path),
+                "Renew deadline must be shorter than the lease duration",
+                "a common choice is lease 15s, renew 10s, retry 2s",
+            );
+        }
+        if election.retry_period >= election.renew_deadline {
+            errors.add(
+                format!("{}.leader_election.retry_period", path),
+                "Retry period must be shorter than the renew deadline",
+            );
         }
     }
+}
+
+fn validate_tls_config(errors: &mut ValidationErrors, path: &str, config: &TlsConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    for (field, value) in [("cert_file", &config.cert_file), ("key_file", &config.key_file)] {
+        if value.as_deref().is_none_or(str::is_empty) {
+            errors.add(format!("{}.{}", path, field), format!("TLS {} must be provided when TLS is enabled", field));
+        }
+    }
+
+    if config.verify_client && config.ca_file.as_deref().is_none_or(str::is_empty) {
+        errors.add_with_hint(
+            format!("{}.ca_file", path),
+            "A CA file is required to verify client certificates",
+            "set tls.ca_file or disable tls.verify_client",
+        );
+    }
+
+    if let Some(version) = &config.min_version {
+        if !matches!(version.as_str(), "TLS1.2" | "TLS1.3") {
+            errors.add_with_hint(
+                format!("{}.min_version", path),
+                format!("Unsupported TLS version \"{}\"", version),
+                "expected TLS1.2 or TLS1.3",
+            );
+        }
+    }
+
+    for (i, protocol) in config.alpn_protocols.iter().enumerate() {
+        if !matches!(protocol.as_str(), "h2" | "http/1.1") {
+            errors.add_with_hint(
+                format!("{}.alpn_protocols[{}]", path, i),
+                format!("Unsupported ALPN protocol \"{}\"", protocol),
+                "expected h2 or http/1.1",
+            );
+        }
+    }
+}
+
+fn validate_observability_config(errors: &mut ValidationErrors, path: &str, config: &ObservabilityConfig) {
+    let tracing = &config.tracing;
+    if tracing.enabled {
+        if !(0.0..=1.0).contains(&tracing.sampling_ratio) {
+            errors.add_with_hint(
+                format!("{}.tracing.sampling_ratio", path),
+                format!("Sampling ratio {} is out of range", tracing.sampling_ratio),
+                "expected a value between 0.0 and 1.0",
+            );
+        }
+        if tracing.service_name.is_empty() {
+            errors.add(format!("{}.tracing.service_name", path), "Tracing service name cannot be empty");
+        }
+        for (i, exporter) in tracing.exporters.iter().enumerate() {
+            let exporter_path = format!("{}.tracing.exporters[{}]", path, i);
+            validate_url(
+                errors,
+                &format!("{}.endpoint", exporter_path),
+                "Exporter endpoint",
+                &exporter.endpoint,
+                &["http", "https", "grpc"],
+            );
+            if exporter.batch_size == 0 {
+                errors.add(format!("{}.batch_size", exporter_path), "Exporter batch size cannot be 0");
+            }
+            if exporter.timeout.is_zero() {
+                errors.add(format!("{}.timeout", exporter_path), "Exporter timeout cannot be 0");
+            }
+        }
+    }
+
+    validate_metrics_config(errors, &format!("{}.metrics", path), &config.metrics);
+    validate_logging_config(errors, &format!("{}.logging", path), &config.logging);
+
+    let health = &config.health;
+    if health.enabled && !health.path.starts_with('/') {
+        errors.add(format!("{}.health.path", path), "Health path must start with '/'");
+    }
+    let mut names = HashSet::new();
+    for (i, check) in health.checks.iter().enumerate() {
+        let check_path = format!("{}.health.checks[{}]", path, i);
+        if check.name.is_empty() {
+            errors.add(format!("{}.name", check_path), "Health check name cannot be empty");
+        } else if !names.insert(check.name.as_str()) {
+            errors.add(format!("{}.name", check_path), format!("Duplicate health check \"{}\"", check.name));
+        }
+        if check.interval.is_zero() {
+            errors.add(format!("{}.interval", check_path), "Health check interval cannot be 0");
+        }
+        if check.timeout > check.interval {
+            errors.add_with_hint(
+                format!("{}.timeout", check_path),
+                "Health check timeout exceeds its interval",
+                "checks would overlap; lower the timeout or raise the interval",
+            );
+        }
+    }
+}
+
+fn validate_url(errors: &mut ValidationErrors, path: &str, what: &str, value: &str, schemes: &[&str]) {
+    if value.is_empty() {
+        errors.add(path, format!("{} cannot be empty", what));
+        return;
+    }
 
-    Ok(())
-} 
\ No newline at end of file
+    match Url::parse(value) {
+        Ok(url) if !schemes.contains(&url.scheme()) => errors.add_with_hint(
+            path,
+            format!("{} \"{}\" has unsupported scheme \"{}\"", what, value, url.scheme()),
+            format!("expected one of {}", schemes.join(", ")),
+        ),
+        Ok(url) if url.host_str().is_none_or(str::is_empty) => {
+            errors.add(path, format!("{} \"{}\" has no host", what, value));
+        }
+        Ok(_) => {}
+        Err(e) => errors.add_with_hint(
+            path,
+            format!("{} \"{}\" is not a valid URL: {}", what, value, e),
+            format!("expected an absolute URL such as {}://service:8080", schemes[0]),
+        ),
+    }
+}
+
+fn validate_forwarding_config(errors: &mut ValidationErrors, path: &str, config: &ForwardingConfig) {
+    for (i, cidr) in config.trusted_proxies.iter().enumerate() {
+        if !is_ip_or_cidr(cidr) {
+            errors.add_with_hint(
+                format!("{}.trusted_proxies[{}]", path, i),
+                format!("Invalid IP address or CIDR range \"{}\"", cidr),
+                "expected an address such as 10.0.0.1 or a range such as 10.0.0.0/8",
+            );
+        }
+    }
+
+    if let Some(via) = &config.via {
+        let is_token = !via.is_empty()
+            && via.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !is_token {
+            errors.add_with_hint(
+                format!("{}.via", path),
+                format!("Invalid Via pseudonym \"{}\"", via),
+                "use letters, digits and punctuation such as - . _ without spaces",
+            );
+        }
+    }
+}
+
+fn validate_retry_budget_config(errors: &mut ValidationErrors, path: &str, config: &RetryBudgetConfig) {
+    if !(0.0..=1000.0).contains(&config.ratio) {
+        errors.add(format!("{}.ratio", path), "Retry budget ratio must be between 0 and 1000");
+    }
+    // tower's `Budget` panics on anything larger
+    if config.min_per_second >= i32::MAX as u32 {
+        errors.add(format!("{}.min_per_second", path), format!("Retry budget min_per_second must be below {}", i32::MAX));
+    }
+    if config.window < Duration::from_secs(1) || config.window > Duration::from_secs(60) {
+        errors.add(format!("{}.window", path), "Retry budget window must be between 1s and 60s");
+    }
+}
+
+fn validate_request_match(errors: &mut ValidationErrors, path: &str, config: &RequestMatchConfig) {
+    for (i, host) in config.hosts.iter().enumerate() {
+        let name = host.strip_prefix("*.").unwrap_or(host);
+        if name.is_empty() || name.contains(['*', '/', ' ']) {
+            errors.add_with_hint(
+                format!("{}.hosts[{}]", path, i),
+                format!("Invalid host pattern \"{}\"", host),
+                "expected a host such as api.example.com or *.example.com",
+            );
+        }
+    }
+
+    for (i, header) in config.headers.iter().enumerate() {
+        if http::HeaderName::from_bytes(header.name.as_bytes()).is_err() {
+            errors.add(format!("{}.headers[{}].name", path, i), format!("Invalid header name \"{}\"", header.name));
+        }
+    }
+
+    for (field, matches) in [("headers", &config.headers), ("query", &config.query)] {
+        for (i, value_match) in matches.iter().enumerate() {
+            if let ValueCondition::Regex(pattern) = &value_match.condition {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    errors.add(format!("{}.{}[{}].regex", path, field, i), format!("Invalid regular expression: {}", e));
+                }
+            }
+        }
+    }
+
+    for (i, cidr) in config.client_cidrs.iter().enumerate() {
+        if !is_ip_or_cidr(cidr) {
+            errors.add_with_hint(
+                format!("{}.client_cidrs[{}]", path, i),
+                format!("Invalid IP address or CIDR range \"{}\"", cidr),
+                "expected an address such as 10.0.0.1 or a range such as 10.0.0.0/8",
+            );
+        }
+    }
+}
+
+/// Names of the parameters an endpoint path captures, including `*` for a
+/// trailing wildcard.
+fn route_params(path: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    for segment in path.split('/') {
+        if let Some(param) = segment.strip_prefix(':') {
+            params.push(param.split_once('<').map_or(param, |(name, _)| name));
+        } else if let Some(name) = segment.strip_prefix('*') {
+            if !name.is_empty() {
+                params.push(name);
+            }
+            params.push(WILDCARD_PARAM);
+        }
+    }
+    params
+}
+
+/// Whether `path` starts with `prefix` followed by a `/` or nothing.
+fn has_path_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn is_ip_or_cidr(value: &str) -> bool {
+    match value.split_once('/') {
+        Some((ip, prefix)) => match (ip.parse::<IpAddr>(), prefix.parse::<u8>()) {
+            (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+            (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+            _ => false,
+        },
+        None => value.parse::<IpAddr>().is_ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::BackendConfig;
+
+    fn backend(url: &str) -> BackendConfig {
+        BackendConfig {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn endpoint(path: &str, backend: Vec<BackendConfig>) -> EndpointConfig {
+        EndpointConfig {
+            path: path.to_string(),
+            method: "GET".to_string(),
+            backend,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert_eq!(validate_config(&Config::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_collects_every_error_with_paths() {
+        let mut config = Config::default();
+        config.server.workers = 0;
+        config.logging.level = "loud".to_string();
+        config.tls.enabled = true;
+        config.tls.cert_file = Some("cert.pem".to_string());
+        config.endpoints = vec![
+            endpoint("/ok", vec![backend("http://users:8080")]),
+            endpoint("orders", vec![backend("http://orders:8080"), backend("not a url")]),
+        ];
+        config.endpoints[0].auth_required = true;
+        config.endpoints[0].guards = vec!["orders:read".to_string()];
+        config.endpoints[0].outlier_detection = Some(serde_json::from_value(serde_json::json!({ "max_ejection_time": "25h" })).unwrap());
+        config.forwarding.trusted_proxies = vec!["10.0.0.0/33".to_string()];
+        config.retry_budget.min_per_second = u32::MAX;
+
+        let errors = validate_config(&config).unwrap_err();
+        let paths: Vec<_> = errors.errors().iter().map(|e| e.path.as_str()).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "server.workers",
+                "logging.level",
+                "endpoints[0].outlier_detection.max_ejection_time",
+                "endpoints[0].auth_required",
+                "endpoints[1].path",
+                "endpoints[1].backend[1].url",
+                "forwarding.trusted_proxies[0]",
+                "retry_budget.min_per_second",
+                "tls.key_file",
+            ]
+        );
+        assert!(errors.to_string().contains("logging.level: Invalid log level \"loud\" (hint: expected one of"));
+    }
+}
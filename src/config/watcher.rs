@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Result, Context};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use super::loader::is_config_file;
+use super::{ConfigSource, Config};
+
+/// Editors usually write a file in several steps (truncate, write, rename),
+/// so events arriving within this window are folded into a single reload.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Watches the configuration file and everything it includes, yielding
+/// freshly loaded and validated configurations whenever any of them changes.
+pub struct ConfigWatcher {
+    source: ConfigSource,
+    events: mpsc::UnboundedReceiver<Vec<PathBuf>>,
+    watcher: RecommendedWatcher,
+    /// Directories currently registered with the OS watcher.
+    directories: HashSet<PathBuf>,
+    /// The config file and its includes; changes to them, or to config files
+    /// inside an included directory, trigger a reload.
+    targets: Vec<PathBuf>,
+}
+
+impl ConfigWatcher {
+    pub fn new(source: ConfigSource) -> Result<Self> {
+        let (tx, events) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            match res {
+                Ok(event) if matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) => {
+                    let _ = tx.send(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => warn!(error = ?e, "Config watcher error"),
+            }
+        })?;
+
+        let mut config_watcher = Self {
+            source,
+            events,
+            watcher,
+            directories: HashSet::new(),
+            targets: Vec::new(),
+        };
+        config_watcher.refresh()?;
+        Ok(config_watcher)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.source.path
+    }
+
+    /// Waits for the next change and reloads every configuration layer.
+    ///
+    /// Returns `None` once the underlying watcher has shut down. A load or
+    /// validation failure is returned as `Some(Err(_))` so callers can keep
+    /// serving the previous configuration.
+    pub async fn changed(&mut self) -> Option<Result<Config>> {
+        loop {
+            let paths = self.events.recv().await?;
+            if paths.iter().any(|path| self.is_relevant(path)) {
+                break;
+            }
+        }
+
+        // Drain the burst of events produced by a single save.
+        loop {
+            match tokio::time::timeout(DEBOUNCE, self.events.recv()).await {
+                Ok(Some(_)) => continue,
+                Ok(None) => return None,
+                Err(_) => break,
+            }
+        }
+
+        debug!(path = %self.path().display(), "Config file changed, reloading");
+        let config = Config::load_from(&self.source);
+
+        // The include list may have changed along with the file
+        if let Err(e) = self.refresh() {
+            warn!(error = ?e, "Failed to update watched config paths");
+        }
+        Some(config)
+    }
+
+    /// Re-reads the include list and registers any new directories.
+    ///
+    /// Directories are watched rather than files: atomic renames replace the
+    /// inode and would silently end a file watch.
+    fn refresh(&mut self) -> Result<()> {
+        let mut targets = vec![std::path::absolute(&self.source.path)?];
+        match self.source.includes() {
+            Ok(includes) => {
+                for include in includes {
+                    targets.push(std::path::absolute(include)?);
+                }
+            }
+            // Keep watching the previous includes until the file parses again
+            Err(_) => targets.extend(self.targets.iter().skip(1).cloned()),
+        }
+
+        let directories: HashSet<PathBuf> = targets
+            .iter()
+            .filter_map(|target| {
+                if target.is_dir() {
+                    Some(target.clone())
+                } else {
+                    target.parent().map(Path::to_path_buf)
+                }
+            })
+            .collect();
+
+        for directory in self.directories.difference(&directories) {
+            let _ = self.watcher.unwatch(directory);
+        }
+        for directory in directories.difference(&self.directories) {
+            self.watcher
+                .watch(directory, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch {}", directory.display()))?;
+        }
+
+        self.directories = directories;
+        self.targets = targets;
+        Ok(())
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        self.targets.iter().any(|target| {
+            path == target
+                || (path.parent() == Some(target.as_path()) && is_config_file(path))
+        })
+    }
+}
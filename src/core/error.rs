@@ -0,0 +1,119 @@
+use std::time::Duration;
+use http::StatusCode;
+
+/// Failures the gateway answers for itself rather than relaying a backend
+/// response. Handlers and middleware return these, usually as the context
+/// of an `anyhow::Error` that keeps the underlying cause for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum GatewayError {
+    #[error("Bad request")]
+    BadRequest,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
+    #[error("Not found")]
+    NotFound,
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+    #[error("Payload too large")]
+    PayloadTooLarge,
+    #[error("Too many requests")]
+    RateLimited {
+        /// When the client may try again, sent as `Retry-After`.
+        retry_after: Option<Duration>,
+    },
+    #[error("Upstream connection failed")]
+    UpstreamConnect,
+    #[error("Bad gateway")]
+    UpstreamFailed,
+    #[error("Circuit open")]
+    CircuitOpen,
+    #[error("Upstream timeout")]
+    UpstreamTimeout,
+    #[error("Internal server error")]
+    Internal,
+}
+
+impl GatewayError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::UpstreamConnect | Self::UpstreamFailed => StatusCode::BAD_GATEWAY,
+            Self::CircuitOpen => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UpstreamTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable identifier of the kind of error, used in problem types.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad-request",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::NotFound => "not-found",
+            Self::MethodNotAllowed => "method-not-allowed",
+            Self::PayloadTooLarge => "payload-too-large",
+            Self::RateLimited { .. } => "rate-limited",
+            Self::UpstreamConnect => "upstream-connect",
+            Self::UpstreamFailed => "upstream-failed",
+            Self::CircuitOpen => "circuit-open",
+            Self::UpstreamTimeout => "upstream-timeout",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Explanation safe to show to clients.
+    pub fn detail(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "The request could not be processed.",
+            Self::Unauthorized => "The request requires authentication.",
+            Self::Forbidden => "The request is not allowed.",
+            Self::NotFound => "No route matches the request.",
+            Self::MethodNotAllowed => "The route does not accept this method.",
+            Self::PayloadTooLarge => "The request body exceeds the size limit.",
+            Self::RateLimited { .. } => "Too many requests, try again later.",
+            Self::UpstreamConnect => "The upstream service could not be reached.",
+            Self::UpstreamFailed => "The upstream service failed to answer.",
+            Self::CircuitOpen => "The upstream service is unavailable.",
+            Self::UpstreamTimeout => "The upstream service did not answer in time.",
+            Self::Internal => "The gateway failed to process the request.",
+        }
+    }
+
+    /// The gateway error `error` stands for: the `GatewayError` it carries,
+    /// or `PayloadTooLarge` when the request body ran over its limit while
+    /// streaming, and `Internal` otherwise.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        if error.chain().any(|cause| cause.is::<http_body_util::LengthLimitError>()) {
+            return Self::PayloadTooLarge;
+        }
+        error.downcast_ref::<Self>().copied().unwrap_or(Self::Internal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_classify() {
+        let timeout = Err::<(), _>(std::io::Error::other("deadline")).context(GatewayError::UpstreamTimeout);
+        assert_eq!(GatewayError::classify(&timeout.unwrap_err()), GatewayError::UpstreamTimeout);
+        assert_eq!(GatewayError::classify(&anyhow::Error::new(GatewayError::CircuitOpen)), GatewayError::CircuitOpen);
+        assert_eq!(GatewayError::classify(&anyhow::anyhow!("boom")), GatewayError::Internal);
+
+        let limited = http_body_util::Limited::new(http_body_util::Full::new(bytes::Bytes::from("too long")), 4);
+        let too_large = anyhow::Error::new(axum::Error::new(limited.collect().await.unwrap_err()));
+        assert_eq!(GatewayError::classify(&too_large), GatewayError::PayloadTooLarge);
+    }
+}
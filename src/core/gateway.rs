@@ -1,28 +1,26 @@
 use std::sync::Arc;
 use anyhow::Result;
 use tokio::sync::RwLock;
-use tracing::{info, debug, error};
-use crate::config::Config;
+use tokio::task::JoinHandle;
+use tracing::{info, debug, error, warn};
+use crate::config::{Config, ConfigSource, ConfigWatcher};
 use crate::protocol::http::{
-    HttpProtocol, HttpClient, HttpServer,
+    Authenticator, Forwarding, HttpProtocol, HttpClient, HttpRouter, HttpServer, RetryBudget,
     middleware::{
         Middleware,
         LoggingMiddleware,
         MetricsMiddleware,
-        AuthMiddleware,
-        RateLimitMiddleware,
     },
 };
-use super::middleware::MiddlewareStack;
-use super::routing::RouterRegistry;
+use crate::telemetry::MetricsServer;
+use super::shutdown::Shutdown;
 
 pub struct Gateway {
     name: String,
     version: String,
-    config: Arc<Config>,
-    router_registry: Arc<RwLock<RouterRegistry>>,
-    middleware_chain: Arc<RwLock<MiddlewareStack>>,
+    config: parking_lot::RwLock<Arc<Config>>,
     http_protocol: Arc<RwLock<HttpProtocol>>,
+    shutdown: Shutdown,
 }
 
 impl Gateway {
@@ -30,10 +28,9 @@ impl Gateway {
         Ok(Self {
             name,
             version,
-            config: Arc::new(config),
-            router_registry: Arc::new(RwLock::new(RouterRegistry::new())),
-            middleware_chain: Arc::new(RwLock::new(MiddlewareStack::new())),
+            config: parking_lot::RwLock::new(Arc::new(config)),
             http_protocol: Arc::new(RwLock::new(HttpProtocol::new())),
+            shutdown: Shutdown::new(),
         })
     }
 
@@ -46,83 +43,162 @@ impl Gateway {
     }
 
     pub fn config(&self) -> Arc<Config> {
-        self.config.clone()
+        self.config.read().clone()
     }
 
-    pub fn router_registry(&self) -> Arc<RwLock<RouterRegistry>> {
-        self.router_registry.clone()
+    pub fn http_protocol(&self) -> Arc<RwLock<HttpProtocol>> {
+        self.http_protocol.clone()
     }
 
-    pub fn middleware_chain(&self) -> Arc<RwLock<MiddlewareStack>> {
-        self.middleware_chain.clone()
+    /// Handle used to stop the gateway. SIGINT and SIGTERM trigger it too
+    /// while the gateway is running.
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
     }
 
+    /// Wires routes, middleware and servers from the current configuration
+    /// and serves until shutdown, draining in-flight requests before it
+    /// returns. Fails if the configuration cannot be applied or a listener
+    /// cannot be bound.
     pub async fn start(&self) -> Result<()> {
         info!("Starting gateway: {} v{}", self.name, self.version);
 
+        self.init().await?;
+        self.http_protocol.read().await.start_health_checks();
+
+        let signals = tokio::spawn(self.shutdown.clone().listen_for_signals());
+
+        // Start protocol servers
+        let result = self.start_servers().await;
+        signals.abort();
+        result?;
+
+        info!("Gateway stopped");
+        Ok(())
+    }
+
+    /// Builds routes and middleware from the current configuration and
+    /// installs them in the shared HTTP protocol.
+    pub async fn init(&self) -> Result<()> {
+        let http = Self::build_http_protocol(&self.config())?;
+
+        // Requests already in flight hold their own handles to the previous
+        // routes and middleware, so swapping here never interrupts them.
+        *self.http_protocol.write().await = http;
+        Ok(())
+    }
+
+    /// Replaces the running configuration. The new routes and middleware are
+    /// built completely before being swapped in, so a failure leaves the
+    /// current configuration untouched. Backends that stay keep their health
+    /// and ejection state.
+    pub async fn reload(&self, config: Config) -> Result<()> {
+        let http = Self::build_http_protocol(&config)?;
+
+        let current = self.config();
+        if current.server.port != config.server.port || current.server.host != config.server.host {
+            warn!("Listener address changes require a restart and were not applied");
+        }
+        if current.server.workers != config.server.workers || current.logging.level != config.logging.level {
+            warn!("Worker count and log level changes require a restart and were not applied");
+        }
+
+        {
+            let mut current = self.http_protocol.write().await;
+            http.inherit_backend_state(&current);
+            *current = http;
+            current.start_health_checks();
+        }
+        *self.config.write() = Arc::new(config);
+        info!(endpoints = self.config().endpoints.len(), "Configuration reloaded");
+        Ok(())
+    }
+
+    /// Spawns a background task that reloads the gateway whenever the config
+    /// file of `source` changes. Invalid configurations are logged and ignored.
+    pub fn watch_config(self: Arc<Self>, source: ConfigSource) -> Result<JoinHandle<()>> {
+        let mut watcher = ConfigWatcher::new(source)?;
+        info!(path = %watcher.path().display(), "Watching configuration for changes");
+
+        Ok(tokio::spawn(async move {
+            while let Some(result) = watcher.changed().await {
+                let outcome = match result {
+                    Ok(config) => self.reload(config).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = outcome {
+                    error!(error = ?e, "Configuration reload failed, keeping current configuration");
+                }
+            }
+        }))
+    }
+
+    fn build_http_protocol(config: &Config) -> Result<HttpProtocol> {
+        let mut http = HttpProtocol::with_router(HttpRouter::with_normalization(config.routing.clone()));
+        http.set_forwarding(Forwarding::new(&config.forwarding)?);
+
         // Initialize telemetry
-        self.init_telemetry().await?;
+        Self::init_telemetry(config, &mut http)?;
 
         // Initialize security
-        self.init_security().await?;
+        Self::init_security(config, &mut http)?;
 
         // Initialize protocols
-        self.init_protocols().await?;
+        Self::init_protocols(config, &mut http)?;
 
-        // Start protocol servers
-        self.start_servers().await?;
-
-        info!("Gateway started successfully");
-        Ok(())
+        Ok(http)
     }
 
-    async fn init_telemetry(&self) -> Result<()> {
+    fn init_telemetry(config: &Config, http: &mut HttpProtocol) -> Result<()> {
         debug!("Initializing telemetry");
 
         // Initialize metrics
-        if self.config.metrics.enabled {
-            let metrics_middleware = self.create_metrics_middleware();
-            self.http_protocol.write().await.add_middleware(metrics_middleware);
+        if config.metrics.enabled {
+            http.add_middleware(Self::create_metrics_middleware());
         }
 
         // Initialize tracing
-        if self.config.observability.tracing.enabled {
-            let tracing_middleware = self.create_tracing_middleware();
-            self.http_protocol.write().await.add_middleware(tracing_middleware);
+        if config.observability.tracing.enabled {
+            http.add_middleware(Self::create_tracing_middleware());
         }
 
         Ok(())
     }
 
-    async fn init_security(&self) -> Result<()> {
+    fn init_security(config: &Config, http: &mut HttpProtocol) -> Result<()> {
         debug!("Initializing security");
 
         // Initialize authentication
-        if self.config.security.auth.enabled {
-            let auth_middleware = self.create_auth_middleware();
-            self.http_protocol.write().await.add_middleware(auth_middleware);
-        }
-
-        // Initialize rate limiting
-        if self.config.security.rate_limit.enabled {
-            let rate_limit_middleware = self.create_rate_limit_middleware();
-            self.http_protocol.write().await.add_middleware(rate_limit_middleware);
+        // The validator requires a secret when auth is enabled; without one
+        // there is no authenticator and auth_required endpoints reject everything
+        if let Some(authenticator) = Self::create_authenticator(config) {
+            http.set_authenticator(authenticator);
         }
 
         Ok(())
     }
 
-    async fn init_protocols(&self) -> Result<()> {
+    fn init_protocols(config: &Config, http: &mut HttpProtocol) -> Result<()> {
         debug!("Initializing protocols");
 
-        let mut http = self.http_protocol.write().await;
-        
+        // One budget caps retries across every endpoint
+        let retry_budget = Arc::new(RetryBudget::new(&config.retry_budget));
+
         // Configure HTTP routes from config
-        for endpoint in &self.config.endpoints {
-            for backend in &endpoint.backend {
-                let client = crate::protocol::http::HttpClient::new(vec![backend.clone()])?;
-                http.router().add_route(&endpoint.path, endpoint.clone(), client)?;
+        for endpoint in &config.endpoints {
+            // Endpoints without a rate limit of their own get the default
+            // one, each with its own buckets
+            let mut endpoint = endpoint.clone();
+            if endpoint.rate_limit.is_none() && config.security.rate_limit.enabled {
+                endpoint.rate_limit = Some(config.security.rate_limit.clone());
+            }
+            let mut client = HttpClient::new(&endpoint.name(), endpoint.backend.clone())?
+                .with_load_balancing(endpoint.load_balancing.clone())
+                .with_retry_budget(retry_budget.clone());
+            if let Some(outlier_detection) = &endpoint.outlier_detection {
+                client = client.with_outlier_detection(outlier_detection.clone());
             }
+            http.router().add_route(&endpoint.path.clone(), endpoint, client)?;
         }
 
         Ok(())
@@ -131,48 +207,188 @@ impl Gateway {
     async fn start_servers(&self) -> Result<()> {
         debug!("Starting protocol servers");
 
-        // Start HTTP server if configured
-        if !self.config.endpoints.is_empty() {
-            self.start_http_server().await?;
-        }
-
-        Ok(())
+        // The metrics listener is bound first so a taken port fails startup
+        // before any traffic is accepted
+        let config = self.config();
+        let metrics = if config.metrics.enabled {
+            Some(MetricsServer::bind(&config.server.host, &config.metrics).await?)
+        } else {
+            None
+        };
+
+        // The HTTP server always runs: it serves the health check, and
+        // endpoints may be added by a later reload. Metrics are served until
+        // it stops.
+        let Some(metrics) = metrics else {
+            return self.start_http_server().await;
+        };
+        let (stop_metrics, http_stopped) = tokio::sync::oneshot::channel();
+        let http = async {
+            let result = self.start_http_server().await;
+            let _ = stop_metrics.send(());
+            result
+        };
+        let metrics = metrics.serve(async move {
+            let _ = http_stopped.await;
+        });
+        let (http, metrics) = tokio::join!(http, metrics);
+        http.and(metrics)
     }
 
     async fn start_http_server(&self) -> Result<()> {
         let server = HttpServer::new(
             self.http_protocol.clone(),
-            self.config.clone(),
+            self.config(),
+            self.shutdown.clone(),
         );
-        
-        tokio::spawn(async move {
-            if let Err(e) = server.start().await {
-                error!(?e, "HTTP server error");
-            }
-        });
 
-        Ok(())
+        server.start().await
     }
 
-    fn create_metrics_middleware(&self) -> Middleware {
+    fn create_metrics_middleware() -> Middleware {
         Middleware::Metrics(MetricsMiddleware)
     }
 
-    fn create_tracing_middleware(&self) -> Middleware {
+    fn create_tracing_middleware() -> Middleware {
         Middleware::Logging(LoggingMiddleware)
     }
 
-    fn create_auth_middleware(&self) -> Middleware {
-        let token = self.config.security.auth.jwt_secret.clone()
-            .unwrap_or_else(|| "default-secret".to_string());
-        Middleware::Auth(AuthMiddleware::new(token))
+    fn create_authenticator(config: &Config) -> Option<Authenticator> {
+        let auth = &config.security.auth;
+        let secret = auth.jwt_secret.clone().filter(|_| auth.enabled)?;
+        Some(
+            Authenticator::new(secret)
+                .with_issuer(auth.jwt_issuer.clone())
+                .with_audience(auth.jwt_audience.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::{BackendConfig, EndpointConfig};
+
+    fn endpoint(path: &str) -> EndpointConfig {
+        EndpointConfig {
+            path: path.to_string(),
+            method: "GET".to_string(),
+            backend: vec![BackendConfig {
+                url: "http://localhost:8080".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_routes() {
+        let mut config = Config {
+            endpoints: vec![endpoint("/users")],
+            ..Default::default()
+        };
+        let gateway = Gateway::new("test".to_string(), "0.0.0".to_string(), config.clone()).unwrap();
+        gateway.init().await.unwrap();
+
+        let http = gateway.http_protocol();
+        assert!(http.read().await.router_ref().match_route(&http::Method::GET, "/users").is_ok());
+
+        config.endpoints = vec![endpoint("/orders")];
+        gateway.reload(config).await.unwrap();
+
+        assert!(http.read().await.router_ref().match_route(&http::Method::GET, "/users").is_err());
+        assert!(http.read().await.router_ref().match_route(&http::Method::GET, "/orders").is_ok());
+        assert_eq!(gateway.config().endpoints[0].path, "/orders");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_keeps_backend_state() {
+        let mut config = Config {
+            endpoints: vec![endpoint("/users")],
+            ..Default::default()
+        };
+        config.endpoints[0].backend[0].health_check = Some(serde_json::from_value(serde_json::json!({})).unwrap());
+        let gateway = Gateway::new("test".to_string(), "0.0.0".to_string(), config.clone()).unwrap();
+        gateway.init().await.unwrap();
+
+        let http = gateway.http_protocol();
+        let backend = http.read().await.backends().next().unwrap().clone();
+        backend.set_healthy(false);
+        backend.eject(std::time::Duration::from_secs(30));
+
+        gateway.reload(config).await.unwrap();
+        let reloaded = http.read().await.backends().next().unwrap().clone();
+        assert!(!Arc::ptr_eq(&backend, &reloaded));
+        assert!(!reloaded.is_healthy());
+        assert!(reloaded.is_ejected());
+    }
+
+    #[tokio::test]
+    async fn test_start_reports_bind_failure() {
+        let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.server.host = "127.0.0.1".to_string();
+        config.server.port = taken.local_addr().unwrap().port();
+        config.metrics.port = 0;
+
+        let gateway = Gateway::new("test".to_string(), "0.0.0".to_string(), config).unwrap();
+        let err = gateway.start().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to bind HTTP server"));
+    }
+
+    #[tokio::test]
+    async fn test_start_returns_after_shutdown() {
+        let mut config = Config::default();
+        config.server.port = free_port();
+        config.server.shutdown_timeout = std::time::Duration::from_secs(1);
+        config.metrics.port = 0;
+
+        let gateway = Arc::new(Gateway::new("test".to_string(), "0.0.0".to_string(), config).unwrap());
+        let running = tokio::spawn({
+            let gateway = gateway.clone();
+            async move { gateway.start().await }
+        });
+
+        gateway.shutdown().trigger();
+        tokio::time::timeout(std::time::Duration::from_secs(5), running)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
     }
 
-    fn create_rate_limit_middleware(&self) -> Middleware {
-        let config = &self.config.security.rate_limit;
-        Middleware::RateLimit(RateLimitMiddleware::new(
-            config.requests_per_second,
-            config.burst,
-        ))
+    fn free_port() -> u16 {
+        let free = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        free.local_addr().unwrap().port()
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_metrics_outlive_the_drain() {
+        let mut config = Config::default();
+        config.server.port = free_port();
+        config.server.drain_delay = std::time::Duration::from_millis(500);
+        config.server.shutdown_timeout = std::time::Duration::from_secs(1);
+        config.metrics.port = free_port();
+        let url = format!("http://127.0.0.1:{}/metrics", config.metrics.port);
+
+        let gateway = Arc::new(Gateway::new("test".to_string(), "0.0.0".to_string(), config).unwrap());
+        let running = tokio::spawn({
+            let gateway = gateway.clone();
+            async move { gateway.start().await }
+        });
+        while reqwest::get(&url).await.is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        gateway.shutdown().trigger();
+        let scrape = reqwest::get(&url).await.unwrap();
+        assert_eq!(scrape.status(), reqwest::StatusCode::OK);
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), running)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(reqwest::get(&url).await.is_err());
+    }
+}
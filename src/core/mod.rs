@@ -1,9 +1,9 @@
+mod error;
 mod gateway;
-mod handler;
-mod middleware;
 mod routing;
+mod shutdown;
 
+pub use error::GatewayError;
 pub use gateway::Gateway;
-pub use handler::{Handler, HandlerResult, Request, Response};
-pub use middleware::{Middleware, MiddlewareStack, Next};
-pub use routing::{Route, Router, RoutingError}; 
\ No newline at end of file
+pub use routing::RoutingError;
+pub use shutdown::Shutdown; 
\ No newline at end of file
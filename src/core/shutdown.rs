@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Shared shutdown state of the gateway.
+///
+/// Once triggered the gateway is draining: the health endpoint reports it so
+/// load balancers stop sending traffic, and the servers stop accepting new
+/// connections while in-flight requests finish.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    draining: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            draining: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Starts draining. Calling it again has no effect.
+    pub fn trigger(&self) {
+        self.draining.send_if_modified(|draining| !std::mem::replace(draining, true));
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Resolves once shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let mut draining = self.draining.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+
+    /// Triggers shutdown on the first SIGINT or SIGTERM.
+    pub async fn listen_for_signals(self) {
+        let ctrl_c = async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                warn!(error = ?e, "Failed to listen for SIGINT");
+                std::future::pending::<()>().await;
+            }
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::terminate()) {
+                Ok(mut sigterm) => {
+                    sigterm.recv().await;
+                }
+                Err(e) => {
+                    warn!(error = ?e, "Failed to listen for SIGTERM");
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => info!("Received SIGINT, shutting down"),
+            _ = terminate => info!("Received SIGTERM, shutting down"),
+            _ = self.triggered() => return,
+        }
+        self.trigger();
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_trigger_wakes_waiters() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_draining());
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.triggered().await }
+        });
+        shutdown.trigger();
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(shutdown.is_draining());
+
+        // Waiting after the fact resolves immediately
+        tokio::time::timeout(Duration::from_secs(1), shutdown.triggered()).await.unwrap();
+    }
+}
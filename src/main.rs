@@ -1,26 +1,48 @@
+use std::process::ExitCode;
 use anyhow::Result;
+use clap::Parser;
 use rustopus::{
-    config::Config,
+    cli::{self, Cli, Command},
+    config::{Config, ConfigSource},
     core::Gateway,
-    protocol::http::{HttpServer, HttpProtocol}
 };
-use tracing::{info, Level};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let source = cli.config_source();
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(source),
+        Command::Validate { file } => Ok(cli::validate(source, file)),
+        Command::PrintConfig { format } => cli::print_config(&source, format),
+        Command::Routes => tokio::runtime::Runtime::new()?.block_on(cli::routes(&source)),
+        Command::Schema => cli::schema(),
+    }
+}
+
+/// Loads the configuration before anything else, so that the log level and
+/// the number of runtime workers can come from it.
+fn serve(source: ConfigSource) -> Result<ExitCode> {
+    let config = Config::load_from(&source)?;
+
     // Initialize logging
     tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
+        .with_env_filter(EnvFilter::new(&config.logging.level))
         .init();
 
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.server.workers)
+        .enable_all()
+        .build()?
+        .block_on(run(source, config))
+}
+
+async fn run(source: ConfigSource, config: Config) -> Result<ExitCode> {
     info!("Starting RustOpus API Gateway...");
-    
-    info!("Loading configuration...");
-    // Load configuration
-    let config = Config::load()?;
-    
+
     // Print configuration details
     info!("Server configuration:");
     info!("  Host: {}", config.server.host);
@@ -34,20 +56,17 @@ async fn main() -> Result<()> {
     info!("Number of configured endpoints: {}", config.endpoints.len());
 
     // Create and start HTTP gateway
-    let gateway = Gateway::new(
+    let gateway = Arc::new(Gateway::new(
         "rustopus".to_string(),
         env!("CARGO_PKG_VERSION").to_string(),
-        config.clone(),
-    )?;
+        config,
+    )?);
 
-    let http: HttpServer = HttpServer::new(
-        Arc::new(RwLock::new(HttpProtocol::new())),
-        Arc::new(config),
-    );
-    
-    // Start the gateway
-    info!("Starting HTTP gateway.....");
-    http.start().await?;
+    // Reload routes and middleware whenever the config file changes
+    let _watcher = gateway.clone().watch_config(source)?;
+
+    // Start the gateway and serve until it stops
+    gateway.start().await?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 } 
\ No newline at end of file
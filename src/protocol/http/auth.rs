@@ -0,0 +1,126 @@
+use http::{header, HeaderMap};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use tracing::debug;
+use crate::config::Secret;
+use crate::core::GatewayError;
+
+/// Verifies the bearer tokens of requests to `auth_required` endpoints
+/// against `security.auth`. It runs before the request body is read, not as
+/// part of the middleware chain.
+#[derive(Debug)]
+pub struct Authenticator {
+    jwt_secret: Secret,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+/// The claims of a bearer token that `guards` are checked against.
+#[derive(Debug, Deserialize)]
+struct Claims {
+    /// Space-separated OAuth scopes.
+    #[serde(default)]
+    scope: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+impl Authenticator {
+    pub fn new(jwt_secret: Secret) -> Self {
+        Self {
+            jwt_secret,
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    /// Only accepts tokens issued by `issuer`.
+    pub fn with_issuer(mut self, issuer: Option<String>) -> Self {
+        self.issuer = issuer;
+        self
+    }
+
+    /// Only accepts tokens meant for `audience`.
+    pub fn with_audience(mut self, audience: Option<String>) -> Self {
+        self.audience = audience;
+        self
+    }
+
+    /// Checks the credentials of a request to an `auth_required` endpoint.
+    /// The request must carry an unexpired HS256 JWT signed with the secret,
+    /// as a bearer token, or it is `Unauthorized`. The token must also grant
+    /// every guard of the endpoint in its `scope` or `roles` claim, or the
+    /// request is `Forbidden`.
+    pub fn authorize(&self, headers: &HeaderMap, guards: &[String]) -> Result<(), GatewayError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .ok_or(GatewayError::Unauthorized)?;
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let key = DecodingKey::from_secret(self.jwt_secret.expose().as_bytes());
+        let claims = jsonwebtoken::decode::<Claims>(token, &key, &validation)
+            .map_err(|e| {
+                debug!(error = %e, "Rejecting bearer token");
+                GatewayError::Unauthorized
+            })?
+            .claims;
+
+        let granted = |guard: &String| claims.scope.split(' ').any(|scope| scope == guard) || claims.roles.contains(guard);
+        if guards.iter().all(granted) {
+            Ok(())
+        } else {
+            Err(GatewayError::Forbidden)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let auth = Authenticator::new(Secret::from("test-secret")).with_issuer(Some("https://issuer".to_string()));
+        assert!(!format!("{:?}", auth).contains("test-secret"));
+        let token = |secret: &str, claims: serde_json::Value| {
+            let key = jsonwebtoken::EncodingKey::from_secret(secret.as_bytes());
+            jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &key).unwrap()
+        };
+        let headers = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+            headers
+        };
+        let exp = jsonwebtoken::get_current_timestamp() + 60;
+        let guards = ["orders:read".to_string()];
+
+        let valid = token("test-secret", serde_json::json!({ "iss": "https://issuer", "exp": exp, "scope": "orders:read orders:write" }));
+        assert_eq!(auth.authorize(&headers(&valid), &guards), Ok(()));
+        let by_role = token("test-secret", serde_json::json!({ "iss": "https://issuer", "exp": exp, "roles": ["orders:read"] }));
+        assert_eq!(auth.authorize(&headers(&by_role), &guards), Ok(()));
+
+        assert_eq!(auth.authorize(&HeaderMap::new(), &guards), Err(GatewayError::Unauthorized));
+        for invalid in [
+            token("other-secret", serde_json::json!({ "iss": "https://issuer", "exp": exp, "scope": "orders:read" })),
+            token("test-secret", serde_json::json!({ "iss": "https://other", "exp": exp, "scope": "orders:read" })),
+            token("test-secret", serde_json::json!({ "iss": "https://issuer", "exp": 1, "scope": "orders:read" })),
+        ] {
+            assert_eq!(auth.authorize(&headers(&invalid), &guards), Err(GatewayError::Unauthorized));
+        }
+
+        let other_scope = token("test-secret", serde_json::json!({ "iss": "https://issuer", "exp": exp, "scope": "orders:write" }));
+        assert_eq!(auth.authorize(&headers(&other_scope), &guards), Err(GatewayError::Forbidden));
+        assert_eq!(auth.authorize(&headers(&other_scope), &[]), Ok(()));
+    }
+}
@@ -0,0 +1,175 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use http_body::{Frame, SizeHint};
+use tokio::time::Instant;
+use tracing::{info, warn};
+use crate::config::types::BackendConfig;
+use super::circuit_breaker::CircuitBreaker;
+
+/// A configured backend together with the state kept about it across
+/// requests. Clients share it, so it lives as long as the configuration
+/// it was built from.
+#[derive(Debug)]
+pub struct Backend {
+    pub config: BackendConfig,
+    /// Name of the endpoint the backend serves, see `EndpointConfig::name`.
+    /// The same URL may back several endpoints, each with its own state.
+    endpoint: String,
+    /// Set when the backend has a `circuit_breaker` configured.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    outstanding: AtomicUsize,
+    /// Cleared while the backend fails its health checks.
+    healthy: AtomicBool,
+    created: Instant,
+    /// End of the current ejection by outlier detection, in nanoseconds
+    /// since `created`.
+    ejected_until: AtomicU64,
+}
+
+/// Counts a request as outstanding on its backend until dropped.
+#[derive(Debug)]
+pub struct InFlight(Arc<Backend>);
+
+/// A response body that keeps its request outstanding until the body has
+/// been read to the end or dropped.
+#[derive(Debug)]
+pub struct TrackedBody<B> {
+    body: B,
+    in_flight: Option<InFlight>,
+}
+
+impl Backend {
+    pub fn new(endpoint: impl Into<String>, config: BackendConfig) -> Self {
+        let endpoint = endpoint.into();
+        let circuit_breaker = config
+            .circuit_breaker
+            .clone()
+            .map(|breaker| CircuitBreaker::new(endpoint.clone(), config.url.clone(), breaker));
+        Self {
+            config,
+            endpoint,
+            circuit_breaker,
+            outstanding: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+            created: Instant::now(),
+            ejected_until: AtomicU64::new(0),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.config.url
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.config.weight.max(1)
+    }
+
+    /// Requests sent to the backend whose response has not been read yet.
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn set_healthy(&self, healthy: bool) {
+        metrics::gauge!(
+            "rustopus_backend_healthy",
+            "endpoint" => self.endpoint.clone(),
+            "backend" => self.config.url.clone(),
+        )
+        .set(f64::from(u8::from(healthy)));
+        if self.healthy.swap(healthy, Ordering::Relaxed) == healthy {
+            return;
+        }
+        if healthy {
+            info!(endpoint = %self.endpoint, backend = %self.config.url, "Backend is healthy again");
+        } else {
+            warn!(endpoint = %self.endpoint, backend = %self.config.url, "Backend is unhealthy, taking it out of rotation");
+        }
+    }
+
+    /// Whether outlier detection currently keeps the backend out of
+    /// rotation.
+    pub fn is_ejected(&self) -> bool {
+        self.since_created() < self.ejected_until.load(Ordering::Relaxed)
+    }
+
+    /// Takes the backend out of rotation for `duration`.
+    pub fn eject(&self, duration: Duration) {
+        let until = self.since_created().saturating_add(duration.as_nanos() as u64);
+        self.ejected_until.store(until, Ordering::Relaxed);
+    }
+
+    /// Whether the load balancer may send requests to the backend.
+    pub fn is_available(&self) -> bool {
+        self.is_healthy() && !self.is_ejected()
+    }
+
+    /// Takes over the health, ejection and open circuit of `previous`, the
+    /// same backend in the configuration a reload replaces, so reloading
+    /// does not put a failing backend back into rotation. Health is only kept
+    /// while the backend still has health checks to restore it.
+    pub fn inherit(&self, previous: &Backend) {
+        if self.config.health_check.is_some() {
+            self.healthy.store(previous.is_healthy(), Ordering::Relaxed);
+        }
+        if let (Some(breaker), Some(previous)) = (&self.circuit_breaker, &previous.circuit_breaker) {
+            breaker.inherit(previous);
+        }
+        let remaining = previous.ejected_until.load(Ordering::Relaxed).saturating_sub(previous.since_created());
+        if remaining > 0 {
+            self.eject(Duration::from_nanos(remaining));
+        }
+    }
+
+    fn since_created(&self) -> u64 {
+        self.created.elapsed().as_nanos() as u64
+    }
+
+    pub fn start_request(self: &Arc<Self>) -> InFlight {
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        InFlight(self.clone())
+    }
+}
+
+impl InFlight {
+    pub fn track<B>(self, body: B) -> TrackedBody<B> {
+        TrackedBody { body, in_flight: Some(self) }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<B: http_body::Body + Unpin> http_body::Body for TrackedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<B::Data>, B::Error>>> {
+        let frame = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(None | Some(Err(_))) = frame {
+            self.in_flight = None;
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
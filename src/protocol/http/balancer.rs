@@ -0,0 +1,287 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use http::header;
+use rand::Rng;
+use crate::config::types::{HashKey, LoadBalancing};
+use super::{Backend, HttpRequest};
+
+/// Points a backend gets on the hash ring per unit of weight.
+const RING_POINTS: u64 = 64;
+
+/// Picks the backend an endpoint's request goes to first. One balancer is
+/// shared by all requests to the endpoint; it never locks, the only state
+/// that changes being atomic counters.
+#[derive(Debug)]
+pub struct LoadBalancer {
+    strategy: LoadBalancing,
+    /// Backend indices in weighted round-robin order.
+    schedule: Vec<usize>,
+    cursor: AtomicUsize,
+    /// Points of the consistent hash ring, sorted by hash.
+    ring: Vec<(u64, usize)>,
+}
+
+impl LoadBalancer {
+    pub fn new(strategy: LoadBalancing, backends: &[Arc<Backend>]) -> Self {
+        let ring = match strategy {
+            LoadBalancing::ConsistentHash(_) => {
+                let mut ring: Vec<_> = backends
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, backend)| {
+                        // Points are placed by URL and weight alone, so
+                        // reordering, adding or removing other backends does
+                        // not move them
+                        (0..RING_POINTS * u64::from(backend.weight()))
+                            .map(move |point| (hash(format!("{}#{}", backend.url(), point).as_bytes()), index))
+                    })
+                    .collect();
+                ring.sort_unstable();
+                ring
+            }
+            _ => Vec::new(),
+        };
+        Self {
+            strategy,
+            schedule: smooth_schedule(&reduced_weights(backends)),
+            cursor: AtomicUsize::new(0),
+            ring,
+        }
+    }
+
+    /// Index into `backends`, the list the balancer was built for, of the
+    /// backend to try first for `request`. Only available backends are
+    /// picked, unless none is.
+    pub fn pick(&self, backends: &[Arc<Backend>], request: &HttpRequest) -> usize {
+        let count = backends.len();
+        if count <= 1 {
+            return 0;
+        }
+        let any_available = backends.iter().any(|backend| backend.is_available());
+        let available = |index: &usize| !any_available || backends[*index].is_available();
+
+        match &self.strategy {
+            LoadBalancing::RoundRobin => self.next_available(available),
+            LoadBalancing::LeastOutstanding => {
+                // Start from a rotating offset so ties are spread out
+                let offset = self.cursor.fetch_add(1, Ordering::Relaxed);
+                (0..count)
+                    .map(|i| (offset + i) % count)
+                    .filter(available)
+                    .min_by(|&a, &b| compare_load(&backends[a], &backends[b]))
+                    .unwrap_or(0)
+            }
+            LoadBalancing::PowerOfTwoChoices => {
+                let candidates: Vec<_> = (0..count).filter(available).collect();
+                let mut rng = rand::thread_rng();
+                let first = candidates[rng.gen_range(0..candidates.len())];
+                if candidates.len() == 1 {
+                    return first;
+                }
+                let second = loop {
+                    let second = candidates[rng.gen_range(0..candidates.len())];
+                    if second != first {
+                        break second;
+                    }
+                };
+                match compare_load(&backends[second], &backends[first]) {
+                    CmpOrdering::Less => second,
+                    _ => first,
+                }
+            }
+            LoadBalancing::Random => {
+                let weight = |index: usize| u64::from(backends[index].weight());
+                let total: u64 = (0..count).filter(available).map(weight).sum();
+                let mut point = rand::thread_rng().gen_range(0..total);
+                (0..count)
+                    .filter(available)
+                    .find(|&index| match point.checked_sub(weight(index)) {
+                        Some(rest) => {
+                            point = rest;
+                            false
+                        }
+                        None => true,
+                    })
+                    .unwrap_or(0)
+            }
+            LoadBalancing::ConsistentHash(key) => match hash_key(key, request) {
+                Some(hash) => {
+                    // Keys of an unavailable backend go to the next one on
+                    // the ring and come back once it recovers
+                    let start = self.ring.partition_point(|&(point, _)| point < hash);
+                    (0..self.ring.len())
+                        .map(|i| self.ring[(start + i) % self.ring.len()].1)
+                        .find(available)
+                        .unwrap_or(0)
+                }
+                None => self.next_available(available),
+            },
+        }
+    }
+
+    /// The next available backend of the round-robin schedule.
+    fn next_available(&self, available: impl Fn(&usize) -> bool) -> usize {
+        let len = self.schedule.len();
+        (0..len)
+            .map(|_| self.schedule[self.cursor.fetch_add(1, Ordering::Relaxed) % len])
+            .find(available)
+            .unwrap_or(0)
+    }
+}
+
+/// Orders backends by requests in flight per unit of weight.
+fn compare_load(a: &Backend, b: &Backend) -> CmpOrdering {
+    let a_load = a.outstanding() as u64 * u64::from(b.weight());
+    let b_load = b.outstanding() as u64 * u64::from(a.weight());
+    a_load.cmp(&b_load)
+}
+
+/// Backend weights divided by their greatest common divisor, which keeps
+/// the schedule as short as possible.
+fn reduced_weights(backends: &[Arc<Backend>]) -> Vec<u64> {
+    let weights: Vec<u64> = backends.iter().map(|backend| u64::from(backend.weight())).collect();
+    let divisor = weights.iter().copied().reduce(gcd).unwrap_or(1).max(1);
+    weights.into_iter().map(|weight| weight / divisor).collect()
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// One full round of smooth weighted round-robin, as done by nginx: each
+/// backend appears as often as its weight, with heavier backends spread
+/// out rather than picked in bursts.
+fn smooth_schedule(weights: &[u64]) -> Vec<usize> {
+    let total: i64 = weights.iter().map(|&weight| weight as i64).sum();
+    let mut current = vec![0i64; weights.len()];
+    let mut schedule = Vec::with_capacity(total as usize);
+    for _ in 0..total {
+        for (current, &weight) in current.iter_mut().zip(weights) {
+            *current += weight as i64;
+        }
+        let Some((picked, _)) = current.iter().enumerate().max_by_key(|&(index, &current)| (current, std::cmp::Reverse(index))) else {
+            break;
+        };
+        current[picked] -= total;
+        schedule.push(picked);
+    }
+    schedule
+}
+
+fn hash_key(key: &HashKey, request: &HttpRequest) -> Option<u64> {
+    match key {
+        HashKey::Header(name) => request.headers.get(name.as_str()).map(|value| hash(value.as_bytes())),
+        HashKey::Cookie(name) => request
+            .headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(cookie, _)| cookie == name)
+            .map(|(_, value)| hash(value.as_bytes())),
+        HashKey::ClientIp => request.client_ip.map(|ip| match ip {
+            IpAddr::V4(ip) => hash(&ip.octets()),
+            IpAddr::V6(ip) => hash(&ip.octets()),
+        }),
+    }
+}
+
+/// 64-bit FNV-1a followed by the MurmurHash3 finalizer, which spreads short
+/// keys over the whole ring. Unlike `DefaultHasher` the result is the same
+/// for every build and platform, so all gateway instances, old and new,
+/// send a key to the same backend.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use crate::config::types::BackendConfig;
+
+    fn backends(weights: &[u32]) -> Vec<Arc<Backend>> {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, &weight)| {
+                let mut config: BackendConfig =
+                    serde_json::from_value(serde_json::json!({ "url": format!("http://app-{}:8080", i) })).unwrap();
+                config.weight = weight;
+                Arc::new(Backend::new("GET /orders", config))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let backends = backends(&[2, 4, 2]);
+        let balancer = LoadBalancer::new(LoadBalancing::RoundRobin, &backends);
+        let picks: Vec<_> = (0..8).map(|_| balancer.pick(&backends, &HttpRequest::default())).collect();
+        assert_eq!(picks, [1, 0, 2, 1, 1, 0, 2, 1]);
+    }
+
+    #[test]
+    fn test_least_outstanding() {
+        let backends = backends(&[1, 1, 1]);
+        let _busy = [backends[0].start_request(), backends[2].start_request(), backends[2].start_request()];
+        for strategy in [LoadBalancing::LeastOutstanding, LoadBalancing::PowerOfTwoChoices] {
+            let balancer = LoadBalancer::new(strategy, &backends);
+            // Two choices only avoid the busiest backend
+            let picked = balancer.pick(&backends, &HttpRequest::default());
+            assert_ne!(picked, 2);
+        }
+        let balancer = LoadBalancer::new(LoadBalancing::LeastOutstanding, &backends);
+        assert_eq!(balancer.pick(&backends, &HttpRequest::default()), 1);
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let backends = backends(&[1, 1, 1]);
+        let balancer = LoadBalancer::new(LoadBalancing::ConsistentHash(HashKey::Cookie("session".to_string())), &backends);
+        let request = |session: &str| {
+            let mut request = HttpRequest::default();
+            let cookie = format!("theme=dark; session={}", session);
+            request.headers.insert(header::COOKIE, HeaderValue::from_str(&cookie).unwrap());
+            request
+        };
+
+        for session in ["a", "b", "c", "d"] {
+            let first = balancer.pick(&backends, &request(session));
+            assert!((0..10).all(|_| balancer.pick(&backends, &request(session)) == first));
+        }
+        let picked: std::collections::HashSet<_> = (0..50).map(|i| balancer.pick(&backends, &request(&i.to_string()))).collect();
+        assert_eq!(picked.len(), 3);
+    }
+
+    #[test]
+    fn test_consistent_hash_is_stable() {
+        // Removing a backend leaves the keys of the others where they were,
+        // whatever the weights
+        let backends = backends(&[2, 4, 3]);
+        let strategy = LoadBalancing::ConsistentHash(HashKey::Header("x-user-id".to_string()));
+        let before = LoadBalancer::new(strategy.clone(), &backends);
+        let after = LoadBalancer::new(strategy, &backends[..2]);
+        for i in 0..100 {
+            let mut request = HttpRequest::default();
+            request.headers.insert("x-user-id", HeaderValue::from(i));
+            let picked = before.pick(&backends, &request);
+            if picked < 2 {
+                assert_eq!(after.pick(&backends[..2], &request), picked);
+            }
+        }
+
+        // Gateway instances built with other toolchains must agree
+        assert_eq!(hash(b"a"), 0x82a2_a958_a9be_ce5b);
+    }
+}
@@ -0,0 +1,157 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use axum::body::Body;
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Frame, SizeHint};
+use parking_lot::Mutex;
+
+/// A streaming request body that can be sent more than once, e.g. to the
+/// next backend after a connection failure.
+///
+/// Data is passed on as it arrives. The first `limit` bytes are also kept,
+/// so a copy made with `try_clone` can replay them before carrying on with
+/// the rest of the stream. Larger bodies stream without being buffered and
+/// can only be sent once.
+#[derive(Debug)]
+pub struct ReplayBody {
+    shared: Arc<Mutex<Shared>>,
+    /// Number of buffered chunks this copy has yielded.
+    position: usize,
+    finished: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    body: Body,
+    chunks: Vec<Bytes>,
+    buffered: usize,
+    limit: usize,
+    /// Cleared once a chunk did not fit in the buffer or the body failed.
+    replayable: bool,
+    /// Set when the body has ended, with its trailers if it had any.
+    end: Option<Option<HeaderMap>>,
+}
+
+impl ReplayBody {
+    pub fn new(body: Body, limit: usize) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                body,
+                chunks: Vec::new(),
+                buffered: 0,
+                limit,
+                replayable: true,
+                end: None,
+            })),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// A copy that starts again from the beginning of the body, unless more
+    /// has been read than could be buffered.
+    pub fn try_clone(&self) -> Option<Self> {
+        self.shared.lock().replayable.then(|| Self {
+            shared: self.shared.clone(),
+            position: 0,
+            finished: false,
+        })
+    }
+}
+
+impl http_body::Body for ReplayBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+        let mut shared = this.shared.lock();
+
+        if let Some(chunk) = shared.chunks.get(this.position) {
+            this.position += 1;
+            return Poll::Ready(Some(Ok(Frame::data(chunk.clone()))));
+        }
+        if let Some(trailers) = &shared.end {
+            this.finished = true;
+            return Poll::Ready(trailers.clone().map(|trailers| Ok(Frame::trailers(trailers))));
+        }
+
+        let frame = match ready!(Pin::new(&mut shared.body).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => {
+                shared.replayable = false;
+                shared.chunks.clear();
+                return Poll::Ready(Some(Err(e)));
+            }
+            None => {
+                shared.end = Some(None);
+                this.finished = true;
+                return Poll::Ready(None);
+            }
+        };
+
+        if let Some(data) = frame.data_ref() {
+            if shared.replayable && shared.buffered + data.len() <= shared.limit {
+                shared.buffered += data.len();
+                shared.chunks.push(data.clone());
+                this.position += 1;
+            } else {
+                shared.replayable = false;
+                shared.chunks.clear();
+            }
+        } else if let Some(trailers) = frame.trailers_ref() {
+            shared.end = Some(Some(trailers.clone()));
+            this.finished = true;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        if self.finished {
+            return true;
+        }
+        let shared = self.shared.lock();
+        shared.chunks.len() <= self.position && (shared.end.is_some() || shared.body.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let shared = self.shared.lock();
+        let pending: u64 = shared.chunks.iter().skip(self.position).map(|chunk| chunk.len() as u64).sum();
+        let rest = if shared.end.is_some() { SizeHint::with_exact(0) } else { shared.body.size_hint() };
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + pending);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + pending);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_replay_body() {
+        let body = ReplayBody::new(Body::from("hello"), 16);
+        let first = body.try_clone().unwrap();
+        assert_eq!(first.size_hint().exact(), Some(5));
+        assert_eq!(first.collect().await.unwrap().to_bytes(), "hello");
+
+        let replay = body.try_clone().unwrap();
+        assert_eq!(replay.size_hint().exact(), Some(5));
+        assert_eq!(replay.collect().await.unwrap().to_bytes(), "hello");
+
+        let large = ReplayBody::new(Body::from("hello"), 4);
+        let first = large.try_clone().unwrap();
+        assert_eq!(first.collect().await.unwrap().to_bytes(), "hello");
+        assert!(large.try_clone().is_none());
+    }
+}
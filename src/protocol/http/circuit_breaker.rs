@@ -0,0 +1,363 @@
+use std::fmt;
+use std::time::Duration;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use crate::config::types::CircuitBreakerConfig;
+
+/// Number of buckets the sliding window is divided into.
+const BUCKETS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally while failures are counted.
+    Closed,
+    /// Requests fail fast until the open duration has passed.
+    Open,
+    /// A limited number of probe requests decide whether to close again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+
+    /// Value of the state gauge: 0 closed, 1 open, 2 half-open.
+    fn gauge(&self) -> f64 {
+        match self {
+            Self::Closed => 0.0,
+            Self::Open => 1.0,
+            Self::HalfOpen => 2.0,
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Circuit breaker for a single backend.
+///
+/// While closed, outcomes are counted in a sliding window split into
+/// buckets. Once the window holds at least `min_requests` outcomes and the
+/// failure rate reaches `failure_rate_threshold` percent, the circuit opens
+/// and requests fail fast. After `open_duration` it goes half-open and lets
+/// `half_open_requests` probes through: if they all succeed it closes,
+/// otherwise it opens again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    endpoint: String,
+    backend: String,
+    config: CircuitBreakerConfig,
+    bucket_width: Duration,
+    created: Instant,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: CircuitState,
+    /// When the circuit last opened.
+    opened_at: Instant,
+    /// Probes let through and probes that succeeded while half-open.
+    probes: u32,
+    probe_successes: u32,
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// Index of the time slice the counts belong to.
+    slice: u64,
+    successes: u32,
+    failures: u32,
+}
+
+/// Permission to send one request, handed out by `CircuitBreaker::acquire`.
+/// Dropping it without recording an outcome, e.g. when the client went
+/// away, frees its probe slot without counting anything.
+#[must_use]
+#[derive(Debug)]
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(endpoint: impl Into<String>, backend: impl Into<String>, config: CircuitBreakerConfig) -> Self {
+        let bucket_width = (config.window / BUCKETS).max(Duration::from_millis(1));
+        let breaker = Self {
+            endpoint: endpoint.into(),
+            backend: backend.into(),
+            config,
+            bucket_width,
+            created: Instant::now(),
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                opened_at: Instant::now(),
+                probes: 0,
+                probe_successes: 0,
+                buckets: vec![Bucket::default(); BUCKETS as usize],
+            }),
+        };
+        breaker.report(CircuitState::Closed);
+        breaker
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().state
+    }
+
+    /// Lets a request through, or returns `None` if the circuit is open or
+    /// all half-open probe slots are taken.
+    pub fn acquire(&self) -> Option<Permit<'_>> {
+        let mut inner = self.inner.lock();
+        if inner.state == CircuitState::Open && inner.opened_at.elapsed() >= self.config.open_duration {
+            inner.probes = 0;
+            inner.probe_successes = 0;
+            self.transition(&mut inner, CircuitState::HalfOpen);
+        }
+        match inner.state {
+            CircuitState::Closed => Some(Permit { breaker: self, probe: false, recorded: false }),
+            CircuitState::Open => None,
+            CircuitState::HalfOpen if inner.probes < self.config.half_open_requests => {
+                inner.probes += 1;
+                Some(Permit { breaker: self, probe: true, recorded: false })
+            }
+            CircuitState::HalfOpen => None,
+        }
+    }
+
+    /// Takes over an open circuit from `previous`, the same backend's
+    /// breaker in the configuration a reload replaces, so the backend keeps
+    /// failing fast for the rest of its open duration. A half-open circuit
+    /// carries over as open since its probes belong to the old breaker, and
+    /// probes again as soon as it is asked. The failure window of a closed
+    /// circuit starts over: its buckets are sized by the old `window`.
+    pub fn inherit(&self, previous: &CircuitBreaker) {
+        let opened_at = {
+            let previous = previous.inner.lock();
+            (previous.state != CircuitState::Closed).then_some(previous.opened_at)
+        };
+        if let Some(opened_at) = opened_at {
+            let mut inner = self.inner.lock();
+            inner.state = CircuitState::Open;
+            inner.opened_at = opened_at;
+            self.report(CircuitState::Open);
+        }
+    }
+
+    fn record(&self, probe: bool, success: bool) {
+        let mut inner = self.inner.lock();
+        match inner.state {
+            CircuitState::HalfOpen if probe => {
+                if !success {
+                    self.open(&mut inner);
+                } else {
+                    inner.probe_successes += 1;
+                    if inner.probe_successes >= self.config.half_open_requests {
+                        inner.buckets.fill(Bucket::default());
+                        self.transition(&mut inner, CircuitState::Closed);
+                    }
+                }
+            }
+            CircuitState::Closed => {
+                let slice = self.slice(Instant::now());
+                let index = (slice % u64::from(BUCKETS)) as usize;
+                let bucket = &mut inner.buckets[index];
+                if bucket.slice != slice {
+                    *bucket = Bucket { slice, ..Default::default() };
+                }
+                if success {
+                    bucket.successes += 1;
+                } else {
+                    bucket.failures += 1;
+                }
+
+                let (successes, failures) = inner
+                    .buckets
+                    .iter()
+                    .filter(|bucket| slice - bucket.slice < u64::from(BUCKETS))
+                    .fold((0u64, 0u64), |(s, f), bucket| (s + u64::from(bucket.successes), f + u64::from(bucket.failures)));
+                let total = successes + failures;
+                if total >= u64::from(self.config.min_requests)
+                    && failures * 100 >= total * u64::from(self.config.failure_rate_threshold)
+                {
+                    self.open(&mut inner);
+                }
+            }
+            // Outcomes of requests let through before the state changed
+            _ => {}
+        }
+    }
+
+    fn release(&self) {
+        let mut inner = self.inner.lock();
+        if inner.state == CircuitState::HalfOpen {
+            inner.probes = inner.probes.saturating_sub(1);
+        }
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        inner.opened_at = Instant::now();
+        self.transition(inner, CircuitState::Open);
+    }
+
+    fn transition(&self, inner: &mut Inner, state: CircuitState) {
+        let from = std::mem::replace(&mut inner.state, state);
+        match state {
+            CircuitState::Open => warn!(endpoint = %self.endpoint, backend = %self.backend, from = %from, "Circuit breaker opened"),
+            _ => info!(endpoint = %self.endpoint, backend = %self.backend, from = %from, to = %state, "Circuit breaker state changed"),
+        }
+        metrics::counter!(
+            "rustopus_circuit_breaker_transitions_total",
+            "endpoint" => self.endpoint.clone(),
+            "backend" => self.backend.clone(),
+            "to" => state.as_str(),
+        )
+        .increment(1);
+        self.report(state);
+    }
+
+    fn report(&self, state: CircuitState) {
+        metrics::gauge!(
+            "rustopus_circuit_breaker_state",
+            "endpoint" => self.endpoint.clone(),
+            "backend" => self.backend.clone(),
+        )
+        .set(state.gauge());
+    }
+
+    /// Index of the bucket-sized time slice `now` falls in. Counting
+    /// starts at `BUCKETS` so unused buckets are always out of the window.
+    fn slice(&self, now: Instant) -> u64 {
+        let since = now.duration_since(self.created);
+        (since.as_nanos() / self.bucket_width.as_nanos()) as u64 + u64::from(BUCKETS)
+    }
+}
+
+impl Permit<'_> {
+    /// Records whether the request succeeded.
+    pub fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.breaker.record(self.probe, success);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            self.breaker.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(
+            "GET /orders",
+            "http://orders:8080",
+            CircuitBreakerConfig {
+                failure_rate_threshold: 50,
+                window: Duration::from_secs(10),
+                min_requests: 4,
+                open_duration: Duration::from_secs(30),
+                half_open_requests: 2,
+            },
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_opens_on_failure_rate() {
+        let breaker = breaker();
+        breaker.acquire().unwrap().record(false);
+        breaker.acquire().unwrap().record(false);
+        breaker.acquire().unwrap().record(false);
+        // Too few requests to judge yet
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.acquire().unwrap().record(true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.acquire().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_old_outcomes_leave_the_window() {
+        let breaker = breaker();
+        for _ in 0..3 {
+            breaker.acquire().unwrap().record(false);
+        }
+        tokio::time::advance(Duration::from_secs(11)).await;
+        for _ in 0..3 {
+            breaker.acquire().unwrap().record(true);
+        }
+        breaker.acquire().unwrap().record(false);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_probes() {
+        let breaker = breaker();
+        for _ in 0..4 {
+            breaker.acquire().unwrap().record(false);
+        }
+        tokio::time::advance(Duration::from_secs(30)).await;
+
+        let first = breaker.acquire().unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        let second = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_none());
+
+        // An abandoned probe frees its slot
+        drop(second);
+        let second = breaker.acquire().unwrap();
+        first.record(true);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        second.record(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        for _ in 0..4 {
+            breaker.acquire().unwrap().record(false);
+        }
+        tokio::time::advance(Duration::from_secs(30)).await;
+        breaker.acquire().unwrap().record(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_keeps_open_circuits() {
+        let previous = breaker();
+        for _ in 0..4 {
+            previous.acquire().unwrap().record(false);
+        }
+        tokio::time::advance(Duration::from_secs(20)).await;
+
+        let reloaded = breaker();
+        reloaded.inherit(&previous);
+        assert_eq!(reloaded.state(), CircuitState::Open);
+        assert!(reloaded.acquire().is_none());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(reloaded.acquire().is_some());
+        assert_eq!(reloaded.state(), CircuitState::HalfOpen);
+
+        // Failures of a closed circuit do not carry over
+        let previous = breaker();
+        for _ in 0..3 {
+            previous.acquire().unwrap().record(false);
+        }
+        let reloaded = breaker();
+        reloaded.inherit(&previous);
+        reloaded.acquire().unwrap().record(false);
+        assert_eq!(reloaded.state(), CircuitState::Closed);
+    }
+}
@@ -1,106 +1,455 @@
 use std::sync::Arc;
-use anyhow::{Result, Context};
-use reqwest::{Client, ClientBuilder};
-use serde_json::Value;
-use tracing::{info, error, instrument};
-use crate::config::types::{BackendConfig, CircuitBreakerConfig, RetryConfig};
+use std::time::Duration;
+use anyhow::{anyhow, Result, Context};
+use axum::body::Body;
+use http::{header, Method, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
+use reqwest::Url;
+use tracing::{info, error, warn, instrument};
+use crate::config::template::{expand_template, WILDCARD_PARAM};
+use crate::config::types::{BackendConfig, LoadBalancing, OutlierDetectionConfig, QueryForwarding};
+use crate::core::GatewayError;
 use async_trait::async_trait;
-use super::HttpHandler;
+use super::retry::{retry_delay, Failure, RetryBudget};
+use super::{Backend, HttpHandler, LoadBalancer, OutlierDetector, HttpRequest, HttpResponse, ReplayBody};
 
-#[derive(Debug, Clone)]
-struct BackendInfo {
-    method: String,
-    url: String,
-}
+/// Characters escaped in each segment of a path parameter substituted into
+/// a backend URL.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'/').add(b'<').add(b'>')
+    .add(b'?').add(b'[').add(b'\\').add(b']').add(b'^').add(b'`')
+    .add(b'{').add(b'|').add(b'}');
+
+/// Used for backends without a `timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request bodies up to this size are buffered while they stream to a
+/// backend, so they can be sent to the next one if that backend fails.
+const REPLAY_BUFFER_LIMIT: usize = 64 * 1024;
 
+type ProxyClient = Client<HttpsConnector<HttpConnector>, ReplayBody>;
+
+/// Proxies requests to a list of backends, starting with the one picked by
+/// the endpoint's load balancer and trying the next one when a backend
+/// cannot be reached and the request body can still be replayed.
+///
+/// Backends that are not available, failing their health checks or ejected
+/// by outlier detection, are skipped unless none is. Backends whose circuit
+/// is open are skipped too; when every circuit is open the request fails
+/// fast with `GatewayError::CircuitOpen`. Backends with a `retry` policy are
+/// retried with backoff before moving on, as long as the shared
+/// `RetryBudget` allows.
 #[derive(Debug)]
 pub struct HttpClient {
-    client: Client,
-    backends: Vec<BackendConfig>,
-    current_backend: usize,
+    client: ProxyClient,
+    backends: Vec<Arc<Backend>>,
+    balancer: LoadBalancer,
+    retry_budget: Arc<RetryBudget>,
+    outlier_detector: Option<OutlierDetector>,
 }
 
 impl HttpClient {
-    pub fn new(backends: Vec<BackendConfig>) -> Result<Self> {
-        let mut builder = ClientBuilder::new()
-            .timeout(std::time::Duration::from_secs(30));
-            
-        if let Some(retry) = backends.first().and_then(|b| b.retry.as_ref()) {
-            builder = configure_retry(builder, retry);
-        }
+    /// Proxies to `backends` on behalf of the endpoint named `endpoint`, see
+    /// `EndpointConfig::name`.
+    pub fn new(endpoint: &str, backends: Vec<BackendConfig>) -> Result<Self> {
+        let client = Client::builder(TokioExecutor::new()).build(HttpsConnector::new());
 
-        let client = builder.build()?;
+        let backends: Vec<_> = backends.into_iter().map(|backend| Arc::new(Backend::new(endpoint, backend))).collect();
 
         Ok(Self {
             client,
+            balancer: LoadBalancer::new(LoadBalancing::default(), &backends),
             backends,
-            current_backend: 0,
+            retry_budget: Arc::default(),
+            outlier_detector: None,
         })
     }
 
-    #[instrument(skip(self, payload))]
-    async fn make_request(&mut self, payload: Value) -> Result<Value> {
+    /// Spreads requests across the backends with `strategy` instead of
+    /// round-robin.
+    pub fn with_load_balancing(mut self, strategy: LoadBalancing) -> Self {
+        self.balancer = LoadBalancer::new(strategy, &self.backends);
+        self
+    }
+
+    /// Draws retries from `budget`, usually shared with the other clients
+    /// of the gateway, instead of a budget of its own.
+    pub fn with_retry_budget(mut self, budget: Arc<RetryBudget>) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// Ejects backends that misbehave in live traffic according to `config`.
+    pub fn with_outlier_detection(mut self, config: OutlierDetectionConfig) -> Self {
+        self.outlier_detector = Some(OutlierDetector::new(config, self.backends.len()));
+        self
+    }
+
+    fn observe(&self, index: usize, success: bool, latency: Option<Duration>) {
+        if let Some(detector) = &self.outlier_detector {
+            detector.record(&self.backends, index, success, latency);
+        }
+    }
+
+    #[instrument(skip(self, request), fields(method = %request.method, path = %request.path))]
+    async fn make_request(&self, mut request: HttpRequest) -> Result<HttpResponse> {
         let mut last_error = None;
-        let client = self.client.clone();
-        let backends = self.backends.clone();
+        let body = ReplayBody::new(std::mem::take(&mut request.body), REPLAY_BUFFER_LIMIT);
+        let backends = &self.backends;
         let total_backends = backends.len();
-        let start_backend = self.current_backend;
-        self.current_backend = (start_backend + 1) % total_backends;
+        let start_backend = self.balancer.pick(backends, &request);
+        self.retry_budget.deposit();
+        // With no backend available, trying them all beats failing outright
+        let any_available = backends.iter().any(|backend| backend.is_available());
 
-        for i in 0..total_backends {
+        'backends: for i in 0..total_backends {
             let backend_idx = (start_backend + i) % total_backends;
-            let backend = &backends[backend_idx];
-            let method = backend.method.as_ref().unwrap_or(&"GET".to_string()).to_string();
-            
-            info!(backend_url = %backend.url, "Attempting request to backend");
-            
-            let mut request = client
-                .request(
-                    reqwest::Method::from_bytes(method.as_bytes())?,
-                    &backend.url
-                );
-
-            // Only add JSON body for non-GET requests
-            if method != "GET" {
-                request = request.json(&payload);
+            if any_available && !backends[backend_idx].is_available() {
+                continue;
             }
-            
-            match request
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        return response.json().await.context("Failed to parse backend response");
+            let backend = &backends[backend_idx].config;
+            let method = match &backend.method {
+                Some(method) => Method::from_bytes(method.as_bytes())
+                    .with_context(|| format!("Invalid backend method {}", method))?,
+                None => request.method.clone(),
+            };
+            let url = backend_url(backend, &request)?;
+            let uri: Uri = url.as_str().parse().with_context(|| format!("Invalid backend URL {}", url))?;
+
+            let mut retries = 0;
+            // Set when retrying after a response, which may have left the
+            // body no longer replayable by the time it is cloned again.
+            let mut next_body = None;
+            loop {
+                let permit = match &backends[backend_idx].circuit_breaker {
+                    Some(breaker) => match breaker.acquire() {
+                        Some(permit) => Some(permit),
+                        None => {
+                            warn!(backend_url = %url, "Circuit open, skipping backend");
+                            continue 'backends;
+                        }
+                    },
+                    None => None,
+                };
+                let Some(attempt_body) = next_body.take().or_else(|| body.try_clone()) else {
+                    break 'backends;
+                };
+
+                info!(backend_url = %url, retries, "Attempting request to backend");
+
+                let mut backend_request = http::Request::builder().method(method.clone()).uri(uri.clone());
+                if let Some(headers) = backend_request.headers_mut() {
+                    // The client sets `Host` from the backend URL
+                    for (name, value) in request.headers.iter().filter(|(name, _)| *name != header::HOST) {
+                        headers.append(name, value.clone());
                     }
-                    last_error = Some(anyhow::anyhow!("Backend returned status: {}", response.status()));
                 }
-                Err(e) => {
-                    error!(error = ?e, "Backend request failed");
-                    last_error = Some(e.into());
+                let backend_request = backend_request.body(attempt_body)?;
+
+                // The timeout covers the wait for the response head; the body
+                // then streams for as long as it takes.
+                let timeout = backend.timeout.unwrap_or(DEFAULT_TIMEOUT);
+                let in_flight = backends[backend_idx].start_request();
+                let started = tokio::time::Instant::now();
+                let failure = match tokio::time::timeout(timeout, self.client.request(backend_request)).await {
+                    Ok(Ok(response)) => {
+                        let success = !response.status().is_server_error();
+                        if let Some(permit) = permit {
+                            permit.record(success);
+                        }
+                        self.observe(backend_idx, success, Some(started.elapsed()));
+                        let failure = Failure::Status(response.status(), response.headers());
+                        let delay = backend.retry.as_ref().and_then(|retry| retry_delay(retry, retries, &method, failure));
+                        if let Some(delay) = delay {
+                            match body.try_clone() {
+                                None => warn!(backend_url = %url, "Request body cannot be replayed, not retrying"),
+                                Some(_) if !self.retry_budget.withdraw() => warn!(backend_url = %url, "Retry budget exhausted"),
+                                Some(retry_body) => {
+                                    warn!(backend_url = %url, status = %response.status(), delay = ?delay, "Retrying backend request");
+                                    count_retry(&backends[backend_idx]);
+                                    drop(response);
+                                    tokio::time::sleep(delay).await;
+                                    next_body = Some(retry_body);
+                                    retries += 1;
+                                    continue;
+                                }
+                            }
+                        }
+                        let (parts, body) = response.into_parts();
+                        return Ok(HttpResponse {
+                            status: parts.status,
+                            version: parts.version,
+                            headers: parts.headers,
+                            body: Body::new(in_flight.track(body)),
+                        });
+                    }
+                    Ok(Err(e)) => {
+                        let connect = e.is_connect();
+                        let e = anyhow::Error::new(e);
+                        // The client's body failing, over its size limit or
+                        // aborted, says nothing about the backend and cannot
+                        // be sent again
+                        if e.chain().any(|cause| cause.is::<axum::Error>()) {
+                            warn!(error = ?e, "Request body failed while streaming to the backend");
+                            return Err(e.context(GatewayError::BadRequest));
+                        }
+                        if let Some(permit) = permit {
+                            permit.record(false);
+                        }
+                        self.observe(backend_idx, false, None);
+                        error!(error = ?e, "Backend request failed");
+                        let (kind, failure) = if connect {
+                            (GatewayError::UpstreamConnect, Failure::Connect)
+                        } else {
+                            (GatewayError::UpstreamFailed, Failure::Other)
+                        };
+                        last_error = Some(e.context(kind));
+                        failure
+                    }
+                    Err(_) => {
+                        if let Some(permit) = permit {
+                            permit.record(false);
+                        }
+                        self.observe(backend_idx, false, Some(timeout));
+                        error!(timeout = ?timeout, "Backend request timed out");
+                        last_error = Some(anyhow!("Backend request timed out after {:?}", timeout).context(GatewayError::UpstreamTimeout));
+                        Failure::Timeout
+                    }
+                };
+
+                let delay = backend.retry.as_ref().and_then(|retry| retry_delay(retry, retries, &method, failure));
+                let Some(delay) = delay else {
+                    break;
+                };
+                if body.try_clone().is_none() {
+                    warn!(backend_url = %url, "Request body cannot be replayed, not retrying");
+                    break;
                 }
+                if !self.retry_budget.withdraw() {
+                    warn!(backend_url = %url, "Retry budget exhausted");
+                    break;
+                }
+                warn!(backend_url = %url, delay = ?delay, "Retrying backend request");
+                count_retry(&backends[backend_idx]);
+                tokio::time::sleep(delay).await;
+                retries += 1;
             }
         }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("All backends failed")))
+        Err(last_error.unwrap_or_else(|| anyhow!("Every backend circuit is open").context(GatewayError::CircuitOpen)))
     }
 }
 
 #[async_trait]
 impl HttpHandler for HttpClient {
-    async fn handle(&self, payload: Value) -> Result<Value> {
-        // Clone self to allow mutation of current_backend
-        let mut client = Self {
-            client: self.client.clone(),
-            backends: self.backends.clone(),
-            current_backend: self.current_backend,
+    async fn handle(&self, request: HttpRequest) -> Result<HttpResponse> {
+        self.make_request(request).await
+    }
+
+    fn backends(&self) -> &[Arc<Backend>] {
+        &self.backends
+    }
+}
+
+fn count_retry(backend: &Backend) {
+    metrics::counter!(
+        "rustopus_backend_retries_total",
+        "endpoint" => backend.endpoint().to_string(),
+        "backend" => backend.url().to_string(),
+    )
+    .increment(1);
+}
+
+/// Builds the URL to call `backend` with for `request`: path parameters are
+/// substituted into the `{name}` placeholders of the configured URL, the
+/// forwarded part of the request path is appended according to
+/// `passthrough`, `strip_prefix` and `add_prefix`, and the query string is
+/// filtered by `forward_query`.
+///
+/// `Url` resolves `.` and `..` segments, escaped or not, so parameters and
+/// forwarded paths holding one are refused rather than letting a request
+/// climb out of the configured backend path.
+pub fn backend_url(backend: &BackendConfig, request: &HttpRequest) -> Result<Url> {
+    if let Some(name) = request.params.iter().find_map(|(name, value)| has_dot_segment(value).then_some(name)) {
+        return Err(anyhow!("Path parameter {} is a dot segment", name).context(GatewayError::BadRequest));
+    }
+    let url = expand_template(&backend.url, |name| {
+        request
+            .params
+            .get(name)
+            .map(|value| encode_param(value))
+    })?;
+    let mut url = Url::parse(&url).with_context(|| format!("Invalid backend URL {}", url))?;
+
+    let forwarded = if backend.passthrough {
+        // Without a trailing wildcard the route matched the whole path
+        Some(request.params.get(WILDCARD_PARAM).map(String::as_str).unwrap_or_default())
+    } else if backend.strip_prefix.is_some() || backend.add_prefix.is_some() {
+        Some(request.path.as_str())
+    } else {
+        None
+    };
+
+    if let Some(forwarded) = forwarded {
+        if has_dot_segment(forwarded) {
+            return Err(anyhow!("Forwarded path {} has a dot segment", forwarded).context(GatewayError::BadRequest));
+        }
+        let forwarded = format!("/{}", forwarded.trim_start_matches('/'));
+        let forwarded = match backend.strip_prefix.as_deref().map(|p| p.trim_end_matches('/')) {
+            Some(prefix) => match forwarded.strip_prefix(prefix) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.to_string(),
+                _ => forwarded,
+            },
+            None => forwarded,
+        };
+        let forwarded = match &backend.add_prefix {
+            Some(prefix) => format!("{}{}", prefix.trim_end_matches('/'), forwarded),
+            None => forwarded,
         };
-        
-        client.make_request(payload).await
+        let path = format!("{}{}", url.path().trim_end_matches('/'), forwarded);
+        url.set_path(if path.is_empty() { "/" } else { &path });
     }
+
+    let incoming = request.query.as_deref().filter(|query| !query.is_empty());
+    match (&backend.forward_query, incoming) {
+        (QueryForwarding::None, _) | (_, None) => {}
+        (QueryForwarding::All, Some(query)) => {
+            let query = match url.query() {
+                Some(existing) if !existing.is_empty() => format!("{}&{}", existing, query),
+                _ => query.to_string(),
+            };
+            url.set_query(Some(&query));
+        }
+        (filter, Some(query)) => {
+            let keep = |name: &str| match filter {
+                QueryForwarding::Only(names) => names.iter().any(|n| n == name),
+                QueryForwarding::Except(names) => !names.iter().any(|n| n == name),
+                _ => unreachable!("handled above"),
+            };
+            let pairs: Vec<_> = form_urlencoded::parse(query.as_bytes())
+                .filter(|(name, _)| keep(name))
+                .collect();
+            if !pairs.is_empty() {
+                url.query_pairs_mut().extend_pairs(pairs);
+            }
+        }
+    }
+
+    Ok(url)
+}
+
+/// Escapes a path parameter for a backend URL. Parameters come from the
+/// normalized request path and may still hold escapes, so each segment is
+/// decoded first rather than escaping its `%` again. `/` separates the
+/// segments of wildcard captures and is kept.
+fn encode_param(value: &str) -> String {
+    let segments: Vec<_> = value
+        .split('/')
+        .map(|segment| {
+            let decoded: Vec<u8> = percent_decode_str(segment).collect();
+            percent_encode(&decoded, PATH_SEGMENT).to_string()
+        })
+        .collect();
+    segments.join("/")
+}
+
+/// Whether any `/`-separated segment of `path` is `.` or `..` once decoded.
+fn has_dot_segment(path: &str) -> bool {
+    path.split('/').any(|segment| {
+        let decoded: Vec<u8> = percent_decode_str(segment).collect();
+        decoded == b"." || decoded == b".."
+    })
 }
 
-fn configure_retry(builder: ClientBuilder, config: &RetryConfig) -> ClientBuilder {
-    // Implement retry configuration
-    builder
-} 
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(url: &str) -> BackendConfig {
+        BackendConfig {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn request(path: &str, query: Option<&str>, params: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            path: path.to_string(),
+            query: query.map(str::to_string),
+            params: params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backend_url_templates_and_query() {
+        let request = request("/users/a b", Some("page=2&sort=name&page=3"), &[("id", "a b")]);
+
+        let url = backend_url(&backend("http://users:8080/users/{id}?src=gw"), &request).unwrap();
+        assert_eq!(url.as_str(), "http://users:8080/users/a%20b?src=gw&page=2&sort=name&page=3");
+
+        let mut only = backend("http://users:8080/users/{id}");
+        only.forward_query = QueryForwarding::Only(vec!["page".to_string()]);
+        assert_eq!(backend_url(&only, &request).unwrap().as_str(), "http://users:8080/users/a%20b?page=2&page=3");
+
+        let mut except = backend("http://users:8080/users");
+        except.forward_query = QueryForwarding::Except(vec!["page".to_string()]);
+        assert_eq!(backend_url(&except, &request).unwrap().as_str(), "http://users:8080/users?sort=name");
+
+        let mut none = backend("http://users:8080/users");
+        none.forward_query = QueryForwarding::None;
+        assert_eq!(backend_url(&none, &request).unwrap().as_str(), "http://users:8080/users");
+
+        assert!(backend_url(&backend("http://users:8080/{missing}"), &request).is_err());
+    }
+
+    #[test]
+    fn test_backend_url_params_are_escaped_once() {
+        // Path normalization leaves escapes in, depending on its settings
+        for id in ["caf%C3%A9", "café"] {
+            let url = backend_url(&backend("http://users:8080/users/{id}"), &request("/users/caf%C3%A9", None, &[("id", id)]));
+            assert_eq!(url.unwrap().as_str(), "http://users:8080/users/caf%C3%A9");
+        }
+
+        // Wildcard captures keep their segments, and escaped slashes stay escaped
+        let url = backend_url(&backend("http://files:8080/{path}"), &request("/files/a%2Fb/c%20d", None, &[("path", "a%2Fb/c%20d")]));
+        assert_eq!(url.unwrap().as_str(), "http://files:8080/a%2Fb/c%20d");
+    }
+
+    #[test]
+    fn test_backend_url_refuses_dot_segments() {
+        let users = backend("http://users:8080/users/{id}/profile");
+        for id in ["..", "%2e%2e", "%2E.", ".", "a/../b"] {
+            let error = backend_url(&users, &request("/users/x", None, &[("id", id)])).unwrap_err();
+            assert_eq!(GatewayError::classify(&error), GatewayError::BadRequest, "{}", id);
+        }
+        let url = backend_url(&users, &request("/users/x", None, &[("id", "...")])).unwrap();
+        assert_eq!(url.as_str(), "http://users:8080/users/.../profile");
+
+        let mut prefixed = backend("http://static:8080/public");
+        prefixed.add_prefix = Some("/v2".to_string());
+        assert!(backend_url(&prefixed, &request("/a/%2e%2e/%2e%2e/admin", None, &[])).is_err());
+    }
+
+    #[test]
+    fn test_backend_url_paths() {
+        let request = request("/api/static/css/site.css", None, &[("*", "css/site.css")]);
+
+        let mut passthrough = backend("http://cdn:8080/assets/");
+        passthrough.passthrough = true;
+        assert_eq!(backend_url(&passthrough, &request).unwrap().as_str(), "http://cdn:8080/assets/css/site.css");
+
+        let mut prefixed = backend("http://static:8080");
+        prefixed.strip_prefix = Some("/api".to_string());
+        prefixed.add_prefix = Some("/v2/".to_string());
+        assert_eq!(backend_url(&prefixed, &request).unwrap().as_str(), "http://static:8080/v2/static/css/site.css");
+
+        // A prefix that does not end at a segment boundary is left alone
+        prefixed.strip_prefix = Some("/ap".to_string());
+        prefixed.add_prefix = None;
+        assert_eq!(backend_url(&prefixed, &request).unwrap().as_str(), "http://static:8080/api/static/css/site.css");
+    }
+}
@@ -0,0 +1,322 @@
+use std::net::IpAddr;
+use anyhow::{Context, Result};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Version};
+use crate::config::types::ForwardingConfig;
+use super::Cidr;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+const PROXY_CONNECTION: HeaderName = HeaderName::from_static("proxy-connection");
+
+/// Headers that only apply to a single connection (RFC 7230 section 6.1)
+/// and must not be passed on by a proxy.
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    KEEP_ALIVE,
+    PROXY_CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Compiled form of `ForwardingConfig`.
+#[derive(Debug, Clone)]
+pub struct Forwarding {
+    trusted_proxies: Vec<Cidr>,
+    x_forwarded: bool,
+    forwarded: bool,
+    via: Option<String>,
+}
+
+impl Forwarding {
+    pub fn new(config: &ForwardingConfig) -> Result<Self> {
+        let trusted_proxies = config
+            .trusted_proxies
+            .iter()
+            .map(|cidr| Cidr::parse(cidr))
+            .collect::<Result<_>>()
+            .context("Invalid trusted proxy")?;
+        Ok(Self {
+            trusted_proxies,
+            x_forwarded: config.x_forwarded,
+            forwarded: config.forwarded,
+            via: config.via.clone(),
+        })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The original client of a request received from `peer`: hops listed in
+    /// `X-Forwarded-For`, or `Forwarded` when that is absent, are followed
+    /// from the right for as long as the address they were received from is
+    /// a trusted proxy.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let hops = if headers.contains_key(X_FORWARDED_FOR) {
+            list(headers, &X_FORWARDED_FOR).map(parse_node).collect()
+        } else {
+            list(headers, &header::FORWARDED)
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.trim().split_once('='))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                        .and_then(|(_, node)| parse_node(node))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            if !self.is_trusted(client) {
+                break;
+            }
+            match hop {
+                Some(ip) => client = ip,
+                // Obfuscated or unknown, so this is as far back as we can see
+                None => break,
+            }
+        }
+        client
+    }
+
+    /// Prepares the headers of a request received from `peer` for a backend:
+    /// strips hop-by-hop headers, replaces forwarding headers that did not
+    /// come from a trusted proxy and appends this hop to them.
+    pub fn forward_request(&self, headers: &mut HeaderMap, peer: Option<IpAddr>, scheme: &str, host: Option<&str>, version: Version) {
+        strip_hop_by_hop(headers);
+
+        let trusted = peer.is_some_and(|peer| self.is_trusted(peer));
+        if !trusted {
+            for name in [X_FORWARDED_FOR, X_FORWARDED_PROTO, X_FORWARDED_HOST, header::FORWARDED] {
+                headers.remove(name);
+            }
+        }
+
+        if self.x_forwarded {
+            if let Some(peer) = peer {
+                append(headers, X_FORWARDED_FOR, &peer.to_string());
+            }
+            if !headers.contains_key(X_FORWARDED_PROTO) {
+                insert(headers, X_FORWARDED_PROTO, scheme);
+            }
+            if let Some(host) = host.filter(|_| !headers.contains_key(X_FORWARDED_HOST)) {
+                insert(headers, X_FORWARDED_HOST, host);
+            }
+        }
+
+        if self.forwarded {
+            let mut element = Vec::new();
+            if let Some(peer) = peer {
+                element.push(format!("for={}", forwarded_node(peer)));
+            }
+            element.push(format!("proto={}", forwarded_value(scheme)));
+            if let Some(host) = host {
+                element.push(format!("host={}", forwarded_value(host)));
+            }
+            append(headers, header::FORWARDED, &element.join(";"));
+        }
+
+        self.add_via(headers, version);
+    }
+
+    /// Prepares the headers of a backend response for the client.
+    pub fn forward_response(&self, headers: &mut HeaderMap, version: Version) {
+        strip_hop_by_hop(headers);
+        self.add_via(headers, version);
+    }
+
+    fn add_via(&self, headers: &mut HeaderMap, version: Version) {
+        if let Some(via) = &self.via {
+            let protocol = match version {
+                Version::HTTP_09 => "0.9",
+                Version::HTTP_10 => "1.0",
+                Version::HTTP_2 => "2",
+                Version::HTTP_3 => "3",
+                _ => "1.1",
+            };
+            append(headers, header::VIA, &format!("{} {}", protocol, via));
+        }
+    }
+}
+
+impl Default for Forwarding {
+    fn default() -> Self {
+        Self::new(&ForwardingConfig::default()).expect("the default forwarding config is valid")
+    }
+}
+
+/// Removes the hop-by-hop headers, including any named in `Connection`.
+/// `TE: trailers` is kept since gRPC backends require it.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<_> = list(headers, &header::CONNECTION)
+        .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+
+    let trailers = list(headers, &header::TE).any(|te| te.eq_ignore_ascii_case("trailers"));
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+    if trailers {
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    }
+}
+
+/// The comma-separated elements of every `name` header.
+fn list<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+}
+
+/// Appends `value` to the comma-separated list in the `name` header.
+fn append(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    let mut elements: Vec<_> = list(headers, &name).collect();
+    elements.push(value);
+    let value = elements.join(", ");
+    insert(headers, name, &value);
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Parses a node such as `192.0.2.1`, `192.0.2.1:8080`, `"[2001:db8::1]:80"`
+/// or `2001:db8::1`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse().ok().or_else(|| node.rsplit_once(':')?.0.parse().ok())
+}
+
+/// `for=` value of RFC 7239, which quotes and brackets IPv6 addresses.
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    }
+}
+
+/// Quotes `value` unless it is a token.
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarding(trusted_proxies: &[&str], forwarded: bool) -> Forwarding {
+        Forwarding::new(&ForwardingConfig {
+            trusted_proxies: trusted_proxies.iter().map(|cidr| cidr.to_string()).collect(),
+            forwarded,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = headers(&[
+            ("connection", "keep-alive, x-session"),
+            ("keep-alive", "timeout=5"),
+            ("x-session", "abc"),
+            ("upgrade", "websocket"),
+            ("te", "trailers, deflate"),
+            ("transfer-encoding", "chunked"),
+            ("content-type", "text/plain"),
+        ]);
+        strip_hop_by_hop(&mut headers);
+
+        let mut names: Vec<_> = headers.keys().map(HeaderName::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["content-type", "te"]);
+        assert_eq!(headers[header::TE], "trailers");
+    }
+
+    #[test]
+    fn test_forward_response_keeps_trailer() {
+        // `Trailer` announces the fields trailing the body end to end, so
+        // it is not a hop-by-hop header
+        let mut headers = headers(&[
+            ("trailer", "grpc-status, grpc-message"),
+            ("transfer-encoding", "chunked"),
+            ("content-type", "application/grpc"),
+        ]);
+        forwarding(&[], false).forward_response(&mut headers, Version::HTTP_2);
+        assert_eq!(headers[header::TRAILER], "grpc-status, grpc-message");
+        assert!(!headers.contains_key(header::TRANSFER_ENCODING));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let forwarding = forwarding(&["10.0.0.0/8"], false);
+        let spoofed = headers(&[("x-forwarded-for", "1.1.1.1, 203.0.113.9, 10.0.0.2")]);
+
+        // Only hops added by trusted proxies are believed
+        assert_eq!(forwarding.client_ip("10.0.0.1".parse().unwrap(), &spoofed), "203.0.113.9".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarding.client_ip("198.51.100.7".parse().unwrap(), &spoofed), "198.51.100.7".parse::<IpAddr>().unwrap());
+
+        let rfc7239 = headers(&[("forwarded", "for=\"[2001:db8::1]:4711\";proto=https, for=10.0.0.3")]);
+        assert_eq!(forwarding.client_ip("10.0.0.1".parse().unwrap(), &rfc7239), "2001:db8::1".parse::<IpAddr>().unwrap());
+
+        let hidden = headers(&[("forwarded", "for=_hidden, for=10.0.0.3")]);
+        assert_eq!(forwarding.client_ip("10.0.0.1".parse().unwrap(), &hidden), "10.0.0.3".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_forward_request() {
+        let incoming = headers(&[
+            ("x-forwarded-for", "203.0.113.9"),
+            ("x-forwarded-proto", "https"),
+            ("forwarded", "for=203.0.113.9"),
+            ("connection", "close"),
+        ]);
+
+        let forwarding = forwarding(&["10.0.0.0/8"], true);
+        let mut trusted = incoming.clone();
+        forwarding.forward_request(&mut trusted, Some("10.0.0.1".parse().unwrap()), "http", Some("api.test"), Version::HTTP_11);
+        assert_eq!(trusted["x-forwarded-for"], "203.0.113.9, 10.0.0.1");
+        assert_eq!(trusted["x-forwarded-proto"], "https");
+        assert_eq!(trusted["x-forwarded-host"], "api.test");
+        assert_eq!(trusted[header::FORWARDED], "for=203.0.113.9, for=10.0.0.1;proto=http;host=api.test");
+        assert_eq!(trusted[header::VIA], "1.1 rustopus");
+        assert!(!trusted.contains_key(header::CONNECTION));
+
+        let mut untrusted = incoming;
+        forwarding.forward_request(&mut untrusted, Some("::1".parse().unwrap()), "http", Some("api.test:8080"), Version::HTTP_2);
+        assert_eq!(untrusted["x-forwarded-for"], "::1");
+        assert_eq!(untrusted["x-forwarded-proto"], "http");
+        assert_eq!(untrusted[header::FORWARDED], "for=\"[::1]\";proto=http;host=\"api.test:8080\"");
+        assert_eq!(untrusted[header::VIA], "2 rustopus");
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use http::Uri;
+use http_body_util::Empty;
+use hyper_tls::HttpsConnector;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+use rand::Rng;
+use tokio::task::JoinHandle;
+use reqwest::Url;
+use tracing::{debug, warn};
+use crate::config::types::{BackendHealthCheck, HealthCheckProtocol};
+use super::Backend;
+
+/// How a backend is probed.
+enum Probe {
+    Http {
+        client: Box<Client<HttpsConnector<HttpConnector>, Empty<Bytes>>>,
+        uri: Uri,
+    },
+    Tcp {
+        host: String,
+        port: u16,
+    },
+}
+
+/// Checks passed and failed in a row.
+#[derive(Debug, Default)]
+struct Streak {
+    passed: u32,
+    failed: u32,
+}
+
+impl Streak {
+    /// Counts the outcome of a check of a backend that is `healthy`, and
+    /// returns its new state once enough checks in a row disagree with it.
+    fn record(&mut self, config: &BackendHealthCheck, healthy: bool, passed: bool) -> Option<bool> {
+        if passed {
+            self.passed = self.passed.saturating_add(1);
+            self.failed = 0;
+            (!healthy && self.passed >= config.healthy_threshold).then_some(true)
+        } else {
+            self.failed = self.failed.saturating_add(1);
+            self.passed = 0;
+            (healthy && self.failed >= config.unhealthy_threshold).then_some(false)
+        }
+    }
+}
+
+/// Starts checking `backend` in the background according to its
+/// `health_check`, if it has one, and returns the task doing so. The checks
+/// stop once the backend is dropped, e.g. after a reload replaced it.
+pub fn spawn_health_check(backend: &Arc<Backend>) -> Option<JoinHandle<()>> {
+    let config = backend.config.health_check.clone()?;
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        warn!(endpoint = %backend.endpoint(), backend = %backend.url(), "Health checks need a Tokio runtime and are disabled");
+        return None;
+    };
+    let probe = match Probe::new(backend.url(), &config) {
+        Ok(probe) => probe,
+        Err(e) => {
+            warn!(endpoint = %backend.endpoint(), backend = %backend.url(), error = ?e, "Health checks are disabled");
+            return None;
+        }
+    };
+    // Report the initial state
+    backend.set_healthy(backend.is_healthy());
+
+    let backend = Arc::downgrade(backend);
+    Some(runtime.spawn(async move {
+        // Start at a random point of the interval so backends configured
+        // together are not all checked at once
+        tokio::time::sleep(scaled(config.interval, 0.0..1.0)).await;
+
+        let mut streak = Streak::default();
+        loop {
+            let result = probe.check(config.timeout).await;
+            let Some(backend) = backend.upgrade() else {
+                break;
+            };
+            if let Err(e) = &result {
+                debug!(endpoint = %backend.endpoint(), backend = %backend.url(), error = ?e, "Health check failed");
+            }
+            if let Some(healthy) = streak.record(&config, backend.is_healthy(), result.is_ok()) {
+                backend.set_healthy(healthy);
+            }
+            drop(backend);
+
+            tokio::time::sleep(scaled(config.interval, 0.9..1.1)).await;
+        }
+    }))
+}
+
+/// `interval` multiplied by a random factor from `range`.
+fn scaled(interval: Duration, range: std::ops::Range<f64>) -> Duration {
+    interval.mul_f64(rand::thread_rng().gen_range(range))
+}
+
+impl Probe {
+    /// Probes the host and port of `url`, a backend URL that may still
+    /// contain `{name}` placeholders in its path.
+    fn new(url: &str, config: &BackendHealthCheck) -> Result<Self> {
+        let mut url = Url::parse(url).with_context(|| format!("Invalid backend URL {}", url))?;
+        match config.protocol {
+            HealthCheckProtocol::Http => {
+                url.set_path(&config.path);
+                url.set_query(None);
+                Ok(Self::Http {
+                    client: Box::new(Client::builder(TokioExecutor::new()).build(HttpsConnector::new())),
+                    uri: url.as_str().parse()?,
+                })
+            }
+            HealthCheckProtocol::Tcp => Ok(Self::Tcp {
+                host: url.host_str().context("Backend URL has no host")?.to_string(),
+                port: url.port_or_known_default().context("Backend URL has no port")?,
+            }),
+        }
+    }
+
+    async fn check(&self, timeout: Duration) -> Result<()> {
+        let check = async {
+            match self {
+                Self::Http { client, uri } => {
+                    let response = client.get(uri.clone()).await?;
+                    let status = response.status();
+                    if !status.is_success() && !status.is_redirection() {
+                        bail!("Health check answered {}", status);
+                    }
+                }
+                Self::Tcp { host, port } => {
+                    tokio::net::TcpStream::connect((host.as_str(), *port)).await?;
+                }
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, check)
+            .await
+            .map_err(|_| anyhow!("Health check timed out after {:?}", timeout))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::BackendConfig;
+
+    fn check(json: serde_json::Value) -> BackendHealthCheck {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let config = check(serde_json::json!({ "healthy_threshold": 2, "unhealthy_threshold": 3 }));
+        let mut streak = Streak::default();
+
+        // Failures in a row take the backend out, a pass in between resets them
+        assert_eq!(streak.record(&config, true, false), None);
+        assert_eq!(streak.record(&config, true, false), None);
+        assert_eq!(streak.record(&config, true, true), None);
+        assert_eq!(streak.record(&config, true, false), None);
+        assert_eq!(streak.record(&config, true, false), None);
+        assert_eq!(streak.record(&config, true, false), Some(false));
+
+        assert_eq!(streak.record(&config, false, false), None);
+        assert_eq!(streak.record(&config, false, true), None);
+        assert_eq!(streak.record(&config, false, true), Some(true));
+        assert_eq!(streak.record(&config, true, true), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_checks_stop_with_their_backend() {
+        // Nothing listens on a port that was just released
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let backend = Arc::new(Backend::new(
+            "GET /orders",
+            BackendConfig {
+                url: closed,
+                health_check: Some(check(serde_json::json!({ "protocol": "tcp", "interval": "1s" }))),
+                ..Default::default()
+            },
+        ));
+        let task = spawn_health_check(&backend).unwrap();
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!task.is_finished());
+
+        // What a reload does to the backends it replaces
+        drop(backend);
+        tokio::time::timeout(Duration::from_secs(60), task).await.unwrap().unwrap();
+    }
+}
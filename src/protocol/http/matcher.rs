@@ -0,0 +1,284 @@
+use std::net::IpAddr;
+use anyhow::{bail, Context, Result};
+use http::{HeaderMap, HeaderName};
+use regex::Regex;
+use crate::config::types::{RequestMatchConfig, ValueCondition, ValueMatch};
+
+/// The parts of a request that routes can match on besides method and path.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RequestInfo<'a> {
+    /// Host the request was sent to, with or without a port.
+    pub host: Option<&'a str>,
+    pub headers: Option<&'a HeaderMap>,
+    /// Raw query string, without the leading `?`.
+    pub query: Option<&'a str>,
+    pub client_ip: Option<IpAddr>,
+}
+
+/// An IPv4 or IPv6 address range such as `10.0.0.0/8`. A bare address is a
+/// range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(value: &str) -> Result<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = addr
+            .parse()
+            .with_context(|| format!("Invalid IP address in \"{}\"", value))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .with_context(|| format!("Invalid prefix length in \"{}\"", value))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Compiled form of an endpoint's `RequestMatchConfig`.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMatcher {
+    hosts: Vec<HostPattern>,
+    headers: Vec<(HeaderName, Condition)>,
+    query: Vec<(String, Condition)>,
+    client_cidrs: Vec<Cidr>,
+}
+
+#[derive(Debug, Clone)]
+enum HostPattern {
+    Exact(String),
+    /// `*.example.com`, stored as `.example.com`.
+    Subdomain(String),
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Exact(String),
+    Regex(Regex),
+    Present(bool),
+}
+
+impl RequestMatcher {
+    pub fn new(config: &RequestMatchConfig) -> Result<Self> {
+        let hosts = config
+            .hosts
+            .iter()
+            .map(|host| {
+                let host = host.to_lowercase();
+                match host.strip_prefix('*') {
+                    Some(suffix) if suffix.starts_with('.') && suffix.len() > 1 => Ok(HostPattern::Subdomain(suffix.to_string())),
+                    Some(_) => bail!("Invalid host pattern \"{}\", wildcards must look like *.example.com", host),
+                    None if host.is_empty() => bail!("Host pattern cannot be empty"),
+                    None => Ok(HostPattern::Exact(host)),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        let headers = config
+            .headers
+            .iter()
+            .map(|header| {
+                let name = HeaderName::from_bytes(header.name.as_bytes())
+                    .with_context(|| format!("Invalid header name \"{}\"", header.name))?;
+                Ok((name, Condition::new(header)?))
+            })
+            .collect::<Result<_>>()?;
+
+        let query = config
+            .query
+            .iter()
+            .map(|param| Ok((param.name.clone(), Condition::new(param)?)))
+            .collect::<Result<_>>()?;
+
+        let client_cidrs = config.client_cidrs.iter().map(|cidr| Cidr::parse(cidr)).collect::<Result<_>>()?;
+
+        Ok(Self { hosts, headers, query, client_cidrs })
+    }
+
+    /// Number of conditions, counting exact hosts twice so they win over
+    /// wildcards; routes with a higher count are tried first.
+    pub fn specificity(&self) -> usize {
+        let hosts = match &self.hosts[..] {
+            [] => 0,
+            hosts if hosts.iter().all(|host| matches!(host, HostPattern::Exact(_))) => 2,
+            _ => 1,
+        };
+        hosts
+            + self.headers.len()
+            + self.query.len()
+            + usize::from(!self.client_cidrs.is_empty())
+    }
+
+    pub fn matches(&self, request: &RequestInfo) -> bool {
+        self.matches_host(request.host)
+            && self.matches_headers(request.headers)
+            && self.matches_query(request.query)
+            && self.matches_client(request.client_ip)
+    }
+
+    fn matches_host(&self, host: Option<&str>) -> bool {
+        if self.hosts.is_empty() {
+            return true;
+        }
+        let Some(host) = host.map(strip_port) else {
+            return false;
+        };
+        let host = host.to_lowercase();
+        self.hosts.iter().any(|pattern| match pattern {
+            HostPattern::Exact(exact) => host == *exact,
+            HostPattern::Subdomain(suffix) => host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+        })
+    }
+
+    fn matches_headers(&self, headers: Option<&HeaderMap>) -> bool {
+        self.headers.iter().all(|(name, condition)| {
+            let values = headers
+                .into_iter()
+                .flat_map(|headers| headers.get_all(name))
+                .filter_map(|value| value.to_str().ok());
+            condition.matches(values)
+        })
+    }
+
+    fn matches_query(&self, query: Option<&str>) -> bool {
+        if self.query.is_empty() {
+            return true;
+        }
+        let pairs: Vec<_> = form_urlencoded::parse(query.unwrap_or_default().as_bytes()).collect();
+        self.query.iter().all(|(name, condition)| {
+            let values = pairs.iter().filter(|(key, _)| key == name).map(|(_, value)| value.as_ref());
+            condition.matches(values)
+        })
+    }
+
+    fn matches_client(&self, ip: Option<IpAddr>) -> bool {
+        if self.client_cidrs.is_empty() {
+            return true;
+        }
+        ip.is_some_and(|ip| self.client_cidrs.iter().any(|cidr| cidr.contains(ip)))
+    }
+}
+
+impl Condition {
+    fn new(config: &ValueMatch) -> Result<Self> {
+        Ok(match &config.condition {
+            ValueCondition::Exact(value) => Self::Exact(value.clone()),
+            ValueCondition::Regex(pattern) => Self::Regex(
+                Regex::new(pattern).with_context(|| format!("Invalid regex for \"{}\"", config.name))?,
+            ),
+            ValueCondition::Present(present) => Self::Present(*present),
+        })
+    }
+
+    /// Whether any of `values` satisfies the condition.
+    fn matches<'a>(&self, mut values: impl Iterator<Item = &'a str>) -> bool {
+        match self {
+            Self::Exact(exact) => values.any(|value| value == exact),
+            Self::Regex(regex) => values.any(|value| regex.is_match(value)),
+            Self::Present(present) => values.next().is_some() == *present,
+        }
+    }
+}
+
+/// `example.com:8080` -> `example.com`, `[::1]:8080` -> `[::1]`.
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') && (host.starts_with('[') || !host[..i].contains(':')) => &host[..i],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn value_match(name: &str, condition: ValueCondition) -> ValueMatch {
+        ValueMatch { name: name.to_string(), condition }
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        assert!(cidr.contains("10.1.200.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("fd00::/8").unwrap().contains("fd12::1".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("nope").is_err());
+    }
+
+    #[test]
+    fn test_request_matcher() {
+        let matcher = RequestMatcher::new(&RequestMatchConfig {
+            hosts: vec!["*.example.com".to_string(), "api.test".to_string()],
+            headers: vec![
+                value_match("x-tenant", ValueCondition::Exact("acme".to_string())),
+                value_match("x-debug", ValueCondition::Present(false)),
+            ],
+            query: vec![value_match("v", ValueCondition::Regex("^2".to_string()))],
+            client_cidrs: vec!["10.0.0.0/8".to_string()],
+        })
+        .unwrap();
+        assert_eq!(matcher.specificity(), 5);
+        let exact_host = RequestMatchConfig {
+            hosts: vec!["a.test".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(RequestMatcher::new(&exact_host).unwrap().specificity(), 2);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant", HeaderValue::from_static("acme"));
+        let request = RequestInfo {
+            host: Some("Shop.Example.com:8443"),
+            headers: Some(&headers),
+            query: Some("a=1&v=2.1"),
+            client_ip: Some("10.0.0.7".parse().unwrap()),
+        };
+        assert!(matcher.matches(&request));
+
+        assert!(!matcher.matches(&RequestInfo { host: Some("example.com"), ..request }));
+        assert!(matcher.matches(&RequestInfo { host: Some("API.test"), ..request }));
+        assert!(!matcher.matches(&RequestInfo { query: Some("v=1"), ..request }));
+        assert!(!matcher.matches(&RequestInfo { client_ip: Some("192.168.0.1".parse().unwrap()), ..request }));
+
+        let mut debug_headers = headers.clone();
+        debug_headers.insert("x-debug", HeaderValue::from_static("1"));
+        assert!(!matcher.matches(&RequestInfo { headers: Some(&debug_headers), ..request }));
+
+        assert!(RequestMatcher::default().matches(&RequestInfo::default()));
+    }
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+}
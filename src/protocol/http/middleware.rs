@@ -1,10 +1,7 @@
 use std::collections::HashMap;
-use std::future::Future;
-use std::pin::Pin;
-use async_trait::async_trait;
+use std::sync::Arc;
 use serde::{Serialize, de::DeserializeOwned};
 use anyhow::Result;
-use tracing::instrument;
 
 pub type HttpContext = HashMap<String, String>;
 
@@ -12,8 +9,6 @@ pub type HttpContext = HashMap<String, String>;
 pub enum Middleware {
     Logging(LoggingMiddleware),
     Metrics(MetricsMiddleware),
-    Auth(AuthMiddleware),
-    RateLimit(RateLimitMiddleware),
 }
 
 impl Middleware {
@@ -24,8 +19,6 @@ impl Middleware {
         match self {
             Middleware::Logging(m) => m.pre_process(request, context).await,
             Middleware::Metrics(m) => m.pre_process(request, context).await,
-            Middleware::Auth(m) => m.pre_process(request, context).await,
-            Middleware::RateLimit(m) => m.pre_process(request, context).await,
         }
     }
 
@@ -36,15 +29,13 @@ impl Middleware {
         match self {
             Middleware::Logging(m) => m.post_process(response, context).await,
             Middleware::Metrics(m) => m.post_process(response, context).await,
-            Middleware::Auth(m) => m.post_process(response, context).await,
-            Middleware::RateLimit(m) => m.post_process(response, context).await,
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct MiddlewareChain {
-    middleware: Vec<Middleware>,
+    middleware: Vec<Arc<Middleware>>,
 }
 
 impl MiddlewareChain {
@@ -55,10 +46,10 @@ impl MiddlewareChain {
     }
 
     pub fn add(&mut self, middleware: Middleware) {
-        self.middleware.push(middleware);
+        self.middleware.push(Arc::new(middleware));
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Middleware> {
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Middleware>> {
         self.middleware.iter()
     }
 }
@@ -67,7 +58,7 @@ impl MiddlewareChain {
 pub struct LoggingMiddleware;
 
 impl LoggingMiddleware {
-    pub async fn pre_process<T>(&self, request: &T, context: &mut HttpContext) -> Result<()>
+    pub async fn pre_process<T>(&self, _request: &T, _context: &mut HttpContext) -> Result<()>
     where
         T: Serialize + Send + Sync,
     {
@@ -75,7 +66,7 @@ impl LoggingMiddleware {
         Ok(())
     }
 
-    pub async fn post_process<R>(&self, response: &R, context: &mut HttpContext) -> Result<()>
+    pub async fn post_process<R>(&self, _response: &R, _context: &mut HttpContext) -> Result<()>
     where
         R: DeserializeOwned + Send + Sync,
     {
@@ -88,7 +79,7 @@ impl LoggingMiddleware {
 pub struct MetricsMiddleware;
 
 impl MetricsMiddleware {
-    pub async fn pre_process<T>(&self, request: &T, context: &mut HttpContext) -> Result<()>
+    pub async fn pre_process<T>(&self, _request: &T, _context: &mut HttpContext) -> Result<()>
     where
         T: Serialize + Send + Sync,
     {
@@ -96,7 +87,7 @@ impl MetricsMiddleware {
         Ok(())
     }
 
-    pub async fn post_process<R>(&self, response: &R, context: &mut HttpContext) -> Result<()>
+    pub async fn post_process<R>(&self, _response: &R, _context: &mut HttpContext) -> Result<()>
     where
         R: DeserializeOwned + Send + Sync,
     {
@@ -105,63 +96,6 @@ impl MetricsMiddleware {
     }
 }
 
-#[derive(Debug)]
-pub struct AuthMiddleware {
-    auth_token: String,
-}
-
-impl AuthMiddleware {
-    pub fn new(auth_token: String) -> Self {
-        Self { auth_token }
-    }
-
-    pub async fn pre_process<T>(&self, request: &T, context: &mut HttpContext) -> Result<()>
-    where
-        T: Serialize + Send + Sync,
-    {
-        context.insert("auth_token".to_string(), self.auth_token.clone());
-        Ok(())
-    }
-
-    pub async fn post_process<R>(&self, response: &R, context: &mut HttpContext) -> Result<()>
-    where
-        R: DeserializeOwned + Send + Sync,
-    {
-        Ok(())
-    }
-}
-
-#[derive(Debug)]
-pub struct RateLimitMiddleware {
-    requests_per_second: u32,
-    burst: u32,
-}
-
-impl RateLimitMiddleware {
-    pub fn new(requests_per_second: u32, burst: u32) -> Self {
-        Self {
-            requests_per_second,
-            burst,
-        }
-    }
-
-    pub async fn pre_process<T>(&self, request: &T, context: &mut HttpContext) -> Result<()>
-    where
-        T: Serialize + Send + Sync,
-    {
-        // TODO: Implement proper rate limiting using a distributed rate limiter
-        // For now, we'll just allow all requests
-        Ok(())
-    }
-
-    pub async fn post_process<R>(&self, response: &R, context: &mut HttpContext) -> Result<()>
-    where
-        R: DeserializeOwned + Send + Sync,
-    {
-        Ok(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,9 +105,18 @@ mod tests {
         let mut chain = MiddlewareChain::new();
         chain.add(Middleware::Logging(LoggingMiddleware));
         chain.add(Middleware::Metrics(MetricsMiddleware));
-        chain.add(Middleware::Auth(AuthMiddleware::new("test-token".to_string())));
-        chain.add(Middleware::RateLimit(RateLimitMiddleware::new(100, 10)));
 
-        // Add test implementation here
+        // Middleware runs in the order it was added
+        let order: Vec<_> = chain.iter().map(|m| matches!(**m, Middleware::Logging(_))).collect();
+        assert_eq!(order, [true, false]);
+
+        let mut context = HttpContext::from([("id".to_string(), "42".to_string())]);
+        for middleware in chain.iter() {
+            middleware.pre_process(&serde_json::json!({ "name": "a" }), &mut context).await.unwrap();
+        }
+        for middleware in chain.iter() {
+            middleware.post_process(&serde_json::Value::Null, &mut context).await.unwrap();
+        }
+        assert_eq!(context, HttpContext::from([("id".to_string(), "42".to_string())]));
     }
-} 
\ No newline at end of file
+}
@@ -1,28 +1,91 @@
+mod auth;
+mod backend;
+mod balancer;
+mod body;
+mod circuit_breaker;
 pub mod client;
+mod forwarding;
+mod health;
+mod matcher;
+mod outlier;
+pub mod problem;
+mod rate_limit;
+mod retry;
 mod router;
 pub mod middleware;
 mod server;
+mod transform;
 
-pub use client::{HttpClient};
-pub use router::HttpRouter;
+pub use auth::Authenticator;
+pub use backend::{Backend, InFlight};
+pub use balancer::LoadBalancer;
+pub use body::ReplayBody;
+pub use circuit_breaker::{CircuitBreaker, CircuitState};
+pub use client::HttpClient;
+pub use forwarding::Forwarding;
+pub use matcher::{Cidr, RequestInfo, RequestMatcher};
+pub use outlier::OutlierDetector;
+pub use rate_limit::RateLimiter;
+pub use retry::RetryBudget;
+pub use router::{HttpRouter, MethodMatcher, ParamType};
 pub use middleware::{Middleware, MiddlewareChain};
 pub use server::HttpServer;
 
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
 use async_trait::async_trait;
-use serde::{Serialize, de::DeserializeOwned};
-use serde_json::Value;
+use axum::body::Body;
+use http::{HeaderMap, Method, StatusCode, Version};
 use anyhow::Result;
 
 pub type HttpContext = std::collections::HashMap<String, String>;
 
+/// A request routed to a handler. The body streams from the client as the
+/// handler reads it, trailers included.
+#[derive(Debug, Default)]
+pub struct HttpRequest {
+    pub method: Method,
+    /// Normalized request path.
+    pub path: String,
+    /// Raw query string, without the leading `?`.
+    pub query: Option<String>,
+    /// Path parameters captured by the route. The part of the path matched
+    /// by a trailing wildcard is also stored under `*`.
+    pub params: HttpContext,
+    pub headers: HeaderMap,
+    /// Address of the client, behind any trusted proxies.
+    pub client_ip: Option<IpAddr>,
+    pub body: Body,
+}
+
+/// A handler's response, relayed to the client as-is. The body streams to
+/// the client as it arrives.
+#[derive(Debug, Default)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    /// HTTP version the backend answered with.
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Body,
+}
+
 #[async_trait]
 pub trait HttpHandler: Send + Sync + std::fmt::Debug + 'static {
-    async fn handle(&self, request: Value) -> Result<Value>;
+    async fn handle(&self, request: HttpRequest) -> Result<HttpResponse>;
+
+    /// Backends the handler proxies to, reported by the health check.
+    fn backends(&self) -> &[Arc<Backend>] {
+        &[]
+    }
 }
 
 pub struct HttpProtocol {
     router: HttpRouter,
     middleware: MiddlewareChain,
+    forwarding: Arc<Forwarding>,
+    /// Set when `security.auth` is enabled.
+    authenticator: Option<Arc<Authenticator>>,
 }
 
 impl HttpProtocol {
@@ -30,6 +93,8 @@ impl HttpProtocol {
         Self {
             router: HttpRouter::new(),
             middleware: MiddlewareChain::new(),
+            forwarding: Arc::default(),
+            authenticator: None,
         }
     }
 
@@ -37,6 +102,15 @@ impl HttpProtocol {
         self.middleware.add(middleware);
     }
 
+    pub fn with_router(router: HttpRouter) -> Self {
+        Self {
+            router,
+            middleware: MiddlewareChain::new(),
+            forwarding: Arc::default(),
+            authenticator: None,
+        }
+    }
+
     pub fn router(&mut self) -> &mut HttpRouter {
         &mut self.router
     }
@@ -48,6 +122,51 @@ impl HttpProtocol {
     pub fn middleware(&self) -> &MiddlewareChain {
         &self.middleware
     }
+
+    pub fn set_forwarding(&mut self, forwarding: Forwarding) {
+        self.forwarding = Arc::new(forwarding);
+    }
+
+    pub fn forwarding(&self) -> &Arc<Forwarding> {
+        &self.forwarding
+    }
+
+    pub fn set_authenticator(&mut self, authenticator: Authenticator) {
+        self.authenticator = Some(Arc::new(authenticator));
+    }
+
+    pub fn authenticator(&self) -> Option<&Arc<Authenticator>> {
+        self.authenticator.as_ref()
+    }
+
+    /// Backends of every route.
+    pub fn backends(&self) -> impl Iterator<Item = &Arc<Backend>> {
+        self.router.routes().iter().flat_map(|route| route.handler.backends())
+    }
+
+    /// Carries the state of the backends of `previous` over to the backends
+    /// of the same endpoint and URL here.
+    pub fn inherit_backend_state(&self, previous: &HttpProtocol) {
+        let previous: HashMap<_, _> = previous
+            .backends()
+            .map(|backend| ((backend.endpoint(), backend.url()), backend))
+            .collect();
+        for backend in self.backends() {
+            if let Some(previous) = previous.get(&(backend.endpoint(), backend.url())) {
+                backend.inherit(previous);
+            }
+        }
+    }
+
+    /// Starts the background health checks of every backend. Only done once
+    /// the protocol serves traffic, so configurations that are only
+    /// inspected or fail to load never probe anything. The checks stop by
+    /// themselves once the protocol is dropped.
+    pub fn start_health_checks(&self) {
+        for backend in self.backends() {
+            health::spawn_health_check(backend);
+        }
+    }
 }
 
 impl Default for HttpProtocol {
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+use crate::config::types::OutlierDetectionConfig;
+use super::Backend;
+
+/// Responses kept per backend for latency outlier detection, unless
+/// `min_requests` asks for more.
+const LATENCY_SAMPLES: usize = 100;
+
+/// Watches the outcome of every request to an endpoint's backends and
+/// ejects those that keep failing or answer too slowly, as long as no more
+/// than `max_ejection_percent` of them are ejected at once.
+#[derive(Debug)]
+pub struct OutlierDetector {
+    config: OutlierDetectionConfig,
+    /// One per backend, in the same order.
+    stats: Vec<Mutex<Stats>>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    consecutive_failures: u32,
+    /// Whether each of the latest responses took longer than the latency
+    /// threshold, oldest first.
+    latencies: VecDeque<bool>,
+    /// Entries of `latencies` that are slow.
+    slow: usize,
+    /// Times the backend has been ejected since it last behaved for
+    /// `max_ejection_time`.
+    ejections: u32,
+    /// When the last ejection ended.
+    ejection_end: Option<Instant>,
+}
+
+impl OutlierDetector {
+    pub fn new(config: OutlierDetectionConfig, backends: usize) -> Self {
+        Self {
+            config,
+            stats: (0..backends).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// Records the outcome of a request to `backends[index]`: whether it
+    /// succeeded, and how long the response took to start if there was one.
+    pub fn record(&self, backends: &[Arc<Backend>], index: usize, success: bool, latency: Option<Duration>) {
+        let backend = &backends[index];
+        if backend.is_ejected() {
+            // Requests sent before the ejection say nothing new
+            return;
+        }
+        let mut stats = self.stats[index].lock();
+
+        if stats.ejection_end.is_some_and(|end| end.elapsed() >= self.config.max_ejection_time) {
+            stats.ejections = 0;
+            stats.ejection_end = None;
+        }
+        if success {
+            stats.consecutive_failures = 0;
+        } else {
+            stats.consecutive_failures += 1;
+        }
+        if let (Some(latency), Some(config)) = (latency, &self.config.latency) {
+            if stats.latencies.len() >= (config.min_requests as usize).max(LATENCY_SAMPLES) && stats.latencies.pop_front() == Some(true) {
+                stats.slow -= 1;
+            }
+            let slow = latency > config.threshold;
+            stats.latencies.push_back(slow);
+            stats.slow += usize::from(slow);
+        }
+
+        let reason = if stats.consecutive_failures >= self.config.consecutive_5xx {
+            "consecutive_5xx"
+        } else if self.latency_exceeded(&stats) {
+            "latency"
+        } else {
+            return;
+        };
+
+        let ejected = backends.iter().filter(|backend| backend.is_ejected()).count();
+        if (ejected + 1) * 100 > self.config.max_ejection_percent as usize * backends.len() {
+            debug!(endpoint = %backend.endpoint(), backend = %backend.url(), reason, "Outlier not ejected, too many backends are ejected already");
+            return;
+        }
+
+        // Each ejection in a row lasts twice as long as the previous one
+        let duration = self
+            .config
+            .base_ejection_time
+            .saturating_mul(1u32 << stats.ejections.min(31))
+            .min(self.config.max_ejection_time);
+        backend.eject(duration);
+        stats.ejections += 1;
+        stats.ejection_end = Some(Instant::now() + duration);
+        stats.consecutive_failures = 0;
+        stats.latencies.clear();
+        stats.slow = 0;
+
+        warn!(endpoint = %backend.endpoint(), backend = %backend.url(), reason, duration = ?duration, "Ejecting outlier backend");
+        metrics::counter!(
+            "rustopus_outlier_ejections_total",
+            "endpoint" => backend.endpoint().to_string(),
+            "backend" => backend.url().to_string(),
+            "reason" => reason,
+        )
+        .increment(1);
+    }
+
+    fn latency_exceeded(&self, stats: &Stats) -> bool {
+        let Some(config) = &self.config.latency else {
+            return false;
+        };
+        let count = stats.latencies.len();
+        if count < config.min_requests as usize {
+            return false;
+        }
+        // The response time at the percentile's rank is above the threshold
+        // exactly when fewer responses than that rank were fast enough, so
+        // counting slow responses as they come in spares sorting them
+        let rank = ((config.percentile / 100.0 * count as f64).ceil() as usize).clamp(1, count);
+        count - stats.slow < rank
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::{BackendConfig, LatencyOutlierConfig};
+
+    fn backends(count: usize) -> Vec<Arc<Backend>> {
+        (0..count)
+            .map(|i| {
+                let config: BackendConfig =
+                    serde_json::from_value(serde_json::json!({ "url": format!("http://app-{}:8080", i) })).unwrap();
+                Arc::new(Backend::new("GET /orders", config))
+            })
+            .collect()
+    }
+
+    fn detector(backends: usize) -> OutlierDetector {
+        OutlierDetector::new(
+            OutlierDetectionConfig {
+                consecutive_5xx: 3,
+                latency: Some(LatencyOutlierConfig {
+                    percentile: 90.0,
+                    threshold: Duration::from_millis(500),
+                    min_requests: 10,
+                }),
+                base_ejection_time: Duration::from_secs(10),
+                max_ejection_time: Duration::from_secs(60),
+                max_ejection_percent: 50,
+            },
+            backends,
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ejects_consecutive_failures_for_longer_each_time() {
+        let backends = backends(2);
+        let detector = detector(2);
+
+        detector.record(&backends, 0, false, None);
+        detector.record(&backends, 0, true, None);
+        detector.record(&backends, 0, false, None);
+        detector.record(&backends, 0, false, None);
+        assert!(!backends[0].is_ejected());
+        detector.record(&backends, 0, false, None);
+        assert!(backends[0].is_ejected());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!backends[0].is_ejected());
+        for _ in 0..3 {
+            detector.record(&backends, 0, false, None);
+        }
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(backends[0].is_ejected());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(!backends[0].is_ejected());
+
+        // Never more than half the pool
+        for _ in 0..3 {
+            detector.record(&backends, 0, false, None);
+            detector.record(&backends, 1, false, None);
+        }
+        assert!(backends[0].is_ejected());
+        assert!(!backends[1].is_ejected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ejects_slow_backends() {
+        let backends = backends(2);
+        let detector = detector(2);
+
+        for i in 0..10 {
+            let latency = if i == 0 { Duration::from_secs(2) } else { Duration::from_millis(50) };
+            detector.record(&backends, 0, true, Some(latency));
+        }
+        assert!(!backends[0].is_ejected());
+        detector.record(&backends, 0, true, Some(Duration::from_secs(1)));
+        assert!(backends[0].is_ejected());
+
+        // Slow responses stop counting once they leave the window
+        detector.record(&backends, 1, true, Some(Duration::from_secs(2)));
+        for _ in 0..LATENCY_SAMPLES {
+            detector.record(&backends, 1, true, Some(Duration::from_millis(50)));
+        }
+        assert_eq!(detector.stats[1].lock().slow, 0);
+    }
+}
@@ -0,0 +1,120 @@
+use axum::response::{IntoResponse, Response};
+use http::{header, HeaderName, HeaderValue};
+use serde_json::json;
+use crate::config::template::render;
+use crate::config::types::ErrorTemplate;
+use crate::core::GatewayError;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Builds the response for `error`: an RFC 7807 `application/problem+json`
+/// body, or the matching entry of `templates` when there is one.
+pub fn error_response(error: &GatewayError, instance: &str, request_id: &str, templates: &[ErrorTemplate]) -> Response {
+    let status = error.status();
+    let problem_type = format!("urn:rustopus:problem:{}", error.code());
+    let title = error.to_string();
+
+    let template = templates
+        .iter()
+        .find(|template| template.status == Some(status.as_u16()))
+        .or_else(|| templates.iter().find(|template| template.status.is_none()));
+
+    let (content_type, body) = match template {
+        Some(template) => {
+            let body = render(&template.body, |name| match name {
+                "status" => Some(status.as_u16().to_string()),
+                "title" => Some(title.clone()),
+                "detail" => Some(error.detail().to_string()),
+                "type" => Some(problem_type.clone()),
+                "instance" => Some(instance.to_string()),
+                "request_id" => Some(request_id.to_string()),
+                _ => None,
+            });
+            (template.content_type.as_str(), body.unwrap_or_default())
+        }
+        None => {
+            let problem = json!({
+                "type": problem_type,
+                "title": title,
+                "status": status.as_u16(),
+                "detail": error.detail(),
+                "instance": instance,
+                "request_id": request_id,
+            });
+            ("application/problem+json", problem.to_string())
+        }
+    };
+
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    if let Ok(content_type) = HeaderValue::from_str(content_type) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    if let Ok(request_id) = HeaderValue::from_str(request_id) {
+        headers.insert(X_REQUEST_ID, request_id);
+    }
+    match error {
+        GatewayError::Unauthorized => {
+            headers.insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        GatewayError::RateLimited { retry_after: Some(retry_after) } => {
+            // Round up so clients never retry early
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        _ => {}
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+
+    async fn body(response: Response) -> String {
+        String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_problem_json() {
+        let response = error_response(&GatewayError::UpstreamTimeout, "/orders/1", "req-1", &[]);
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/problem+json");
+        assert_eq!(response.headers()[X_REQUEST_ID], "req-1");
+
+        let problem: Value = serde_json::from_str(&body(response).await).unwrap();
+        assert_eq!(problem["type"], "urn:rustopus:problem:upstream-timeout");
+        assert_eq!(problem["status"], 504);
+        assert_eq!(problem["instance"], "/orders/1");
+        assert_eq!(problem["request_id"], "req-1");
+    }
+
+    #[tokio::test]
+    async fn test_error_templates() {
+        let templates = [
+            ErrorTemplate {
+                status: None,
+                content_type: "text/plain".to_string(),
+                body: "{{status}} {{title}} ({{request_id}})".to_string(),
+            },
+            ErrorTemplate {
+                status: Some(503),
+                content_type: "text/html".to_string(),
+                body: "<h1>{{ detail }}</h1>".to_string(),
+            },
+        ];
+
+        let response = error_response(&GatewayError::CircuitOpen, "/", "req-2", &templates);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(body(response).await, "<h1>The upstream service is unavailable.</h1>");
+
+        let response = error_response(&GatewayError::UpstreamConnect, "/", "req-2", &templates);
+        assert_eq!(body(response).await, "502 Upstream connection failed (req-2)");
+
+        let response = error_response(&GatewayError::RateLimited { retry_after: Some(std::time::Duration::from_millis(1500)) }, "/", "req-3", &[]);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
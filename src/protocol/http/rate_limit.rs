@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+use crate::config::types::RateLimitConfig;
+use crate::core::GatewayError;
+
+/// Idle buckets are dropped once a limiter tracks this many clients.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Limits the requests each client sends to one endpoint with a token
+/// bucket: it holds up to `burst` tokens, at least one, refilled at
+/// `requests_per_second`, and each request takes one. Clients are told
+/// apart by their address behind any trusted proxies. The limit applies to
+/// this gateway instance only.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<Option<IpAddr>, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        let refill = now.duration_since(self.refilled).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(burst);
+        self.refilled = now;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            rate: f64::from(config.requests_per_second.max(1)),
+            burst: f64::from(config.burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of `client`, or fails with the time
+    /// until the next one is available.
+    pub fn check(&self, client: Option<IpAddr>) -> Result<(), GatewayError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= PRUNE_THRESHOLD && !buckets.contains_key(&client) {
+            // A full bucket is no different from a new one
+            buckets.retain(|_, bucket| {
+                bucket.refill(now, self.rate, self.burst);
+                bucket.tokens < self.burst
+            });
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket { tokens: self.burst, refilled: now });
+        bucket.refill(now, self.rate, self.burst);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate);
+        Err(GatewayError::RateLimited { retry_after: Some(retry_after) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit_per_client() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            enabled: true,
+            requests_per_second: 2,
+            burst: 3,
+        });
+        let client: Option<IpAddr> = Some("10.0.0.1".parse().unwrap());
+        let other: Option<IpAddr> = Some("10.0.0.2".parse().unwrap());
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(client), Ok(()));
+        }
+        assert_eq!(limiter.check(client), Err(GatewayError::RateLimited { retry_after: Some(Duration::from_millis(500)) }));
+        assert_eq!(limiter.check(other), Ok(()));
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(limiter.check(client), Ok(()));
+        assert!(limiter.check(client).is_err());
+    }
+}
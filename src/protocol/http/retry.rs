@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime};
+use http::{header, HeaderMap, Method, StatusCode};
+use rand::Rng;
+use tower::retry::budget::Budget;
+use crate::config::types::{RetryBudgetConfig, RetryCondition, RetryConfig};
+
+/// Retries shared by all backends. Every request deposits into the budget
+/// and every retry withdraws from it, so retries stay a fraction of the
+/// traffic even when many backends fail at once.
+#[derive(Debug)]
+pub struct RetryBudget(Budget);
+
+impl RetryBudget {
+    pub fn new(config: &RetryBudgetConfig) -> Self {
+        Self(Budget::new(config.window, config.min_per_second, config.ratio))
+    }
+
+    pub fn deposit(&self) {
+        self.0.deposit();
+    }
+
+    /// Takes one retry from the budget, or returns false if it is spent.
+    pub fn withdraw(&self) -> bool {
+        self.0.withdraw().is_ok()
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(&RetryBudgetConfig::default())
+    }
+}
+
+/// How an attempt to call a backend failed.
+#[derive(Debug, Clone, Copy)]
+pub enum Failure<'a> {
+    /// The connection could not be established, so the request was not sent.
+    Connect,
+    Timeout,
+    /// The backend answered with this status and headers.
+    Status(StatusCode, &'a HeaderMap),
+    /// Anything else, such as a connection dropped mid-request.
+    Other,
+}
+
+/// How long to wait before retrying a `method` request that failed with
+/// `failure` after `retries` retries, or `None` if it should not be retried.
+pub fn retry_delay(config: &RetryConfig, retries: u32, method: &Method, failure: Failure<'_>) -> Option<Duration> {
+    if retries >= config.attempts {
+        return None;
+    }
+    let retryable = match failure {
+        Failure::Connect => config.retry_on.contains(&RetryCondition::ConnectError),
+        Failure::Timeout => config.retry_on.contains(&RetryCondition::Timeout),
+        Failure::Status(status, _) => {
+            let gateway_error = matches!(
+                status,
+                StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+            );
+            (gateway_error && config.retry_on.contains(&RetryCondition::GatewayError))
+                || config.statuses.contains(&status.as_u16())
+        }
+        Failure::Other => false,
+    };
+    // A request that never got through is safe to send again
+    let safe = matches!(failure, Failure::Connect) || config.non_idempotent || is_idempotent(method);
+    if !retryable || !safe {
+        return None;
+    }
+
+    match failure {
+        // Waiting longer than the backend asks for is fine, but a backend
+        // that asks for more than `max_backoff` is not retried at all.
+        Failure::Status(_, headers) => match retry_after(headers) {
+            Some(delay) if delay > config.max_backoff => None,
+            Some(delay) => Some(delay),
+            None => Some(backoff(config, retries)),
+        },
+        _ => Some(backoff(config, retries)),
+    }
+}
+
+/// Exponential backoff with full jitter: a random delay up to `backoff`
+/// doubled `retries` times, capped at `max_backoff`.
+fn backoff(config: &RetryConfig, retries: u32) -> Duration {
+    let ceiling = config
+        .backoff
+        .saturating_mul(1u32 << retries.min(31))
+        .min(config.max_backoff);
+    rand::thread_rng().gen_range(Duration::ZERO..=ceiling)
+}
+
+/// The delay a `Retry-After` header asks for, given in seconds or as a date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            attempts: 2,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            retry_on: vec![RetryCondition::ConnectError, RetryCondition::GatewayError],
+            statuses: vec![429],
+            non_idempotent: false,
+        }
+    }
+
+    #[test]
+    fn test_retry_conditions() {
+        let config = config();
+        let headers = HeaderMap::new();
+        let status = |code: u16| Failure::Status(StatusCode::from_u16(code).unwrap(), &headers);
+
+        assert!(retry_delay(&config, 0, &Method::GET, Failure::Connect).is_some());
+        assert!(retry_delay(&config, 0, &Method::GET, status(503)).is_some());
+        assert!(retry_delay(&config, 0, &Method::GET, status(429)).is_some());
+        assert!(retry_delay(&config, 0, &Method::GET, status(500)).is_none());
+        assert!(retry_delay(&config, 0, &Method::GET, Failure::Timeout).is_none());
+        assert!(retry_delay(&config, 2, &Method::GET, Failure::Connect).is_none());
+
+        // POST is only retried when it cannot have reached the backend
+        assert!(retry_delay(&config, 0, &Method::POST, Failure::Connect).is_some());
+        assert!(retry_delay(&config, 0, &Method::POST, status(503)).is_none());
+        let config = RetryConfig { non_idempotent: true, ..config };
+        assert!(retry_delay(&config, 0, &Method::POST, status(503)).is_some());
+    }
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let config = config();
+        for retries in 0..2 {
+            let delay = retry_delay(&config, retries, &Method::GET, Failure::Connect).unwrap();
+            assert!(delay <= Duration::from_millis(100 << retries));
+        }
+        assert!(backoff(&config, 10) <= config.max_backoff);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        let delay = retry_delay(&config, 0, &Method::GET, Failure::Status(StatusCode::SERVICE_UNAVAILABLE, &headers));
+        assert_eq!(delay, Some(Duration::from_secs(1)));
+
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("120"));
+        assert!(retry_delay(&config, 0, &Method::GET, Failure::Status(StatusCode::SERVICE_UNAVAILABLE, &headers)).is_none());
+    }
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(&RetryBudgetConfig {
+            ratio: 0.5,
+            min_per_second: 0,
+            window: Duration::from_secs(10),
+        });
+        assert!(!budget.withdraw());
+        budget.deposit();
+        budget.deposit();
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+    }
+}
@@ -1,133 +1,649 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
-use async_trait::async_trait;
+use http::Method;
+use anyhow::{bail, Context, Result};
 use regex::Regex;
-use anyhow::{Result, Context};
 use tracing::{debug, instrument};
-use crate::config::types::EndpointConfig;
-use super::{HttpHandler, HttpClient};
+use crate::config::template::WILDCARD_PARAM;
+use crate::config::types::{EndpointConfig, PercentDecoding, RequestMatchConfig, RoutingConfig, TrailingSlash};
+use crate::core::RoutingError;
+use super::{HttpHandler, RateLimiter};
+use super::matcher::{RequestInfo, RequestMatcher};
+
+/// The request methods a route answers to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MethodMatcher {
+    /// `ANY` in the configuration: every method.
+    Any,
+    Only(Method),
+}
+
+impl MethodMatcher {
+    pub fn parse(method: &str) -> Result<Self> {
+        let method = method.to_uppercase();
+        if method == "ANY" {
+            return Ok(Self::Any);
+        }
+        Method::from_bytes(method.as_bytes())
+            .map(Self::Only)
+            .with_context(|| format!("Invalid HTTP method \"{}\"", method))
+    }
+
+    pub fn matches(&self, method: &Method) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Only(only) => only == method,
+        }
+    }
+}
+
+impl fmt::Display for MethodMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("ANY"),
+            Self::Only(method) => f.write_str(method.as_str()),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Route {
-    pub(crate) pattern: Regex,
+    pub(crate) method: MethodMatcher,
+    pub(crate) matcher: RequestMatcher,
     pub(crate) handler: Arc<dyn HttpHandler>,
+    /// Set when `config.rate_limit` is enabled.
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
     pub(crate) config: EndpointConfig,
 }
 
+/// Routes compiled into a tree of path segments, keyed by method at each
+/// leaf so one path can send each method to different backends.
+///
+/// Matching walks the request path segment by segment and prefers the most
+/// specific route regardless of the order routes were added in:
+///
+/// 1. a static segment (`/users/me`),
+/// 2. a typed parameter (`/users/:id<int>`), in the order they were added,
+/// 3. a plain parameter (`/users/:id`),
+/// 4. a trailing wildcard (`/users/*` or `/users/*rest`), which matches one
+///    or more segments.
+///
+/// Several routes can share a method and path when they have different
+/// request conditions (`EndpointConfig::matches`); those with more conditions
+/// (and exact hosts over wildcards) are tried first, then in the order they
+/// were added. When a more specific branch has no route for the rest of the
+/// path, the request method or the request's host, headers, query or client
+/// address, matching falls back to the next one. Each node of the tree is
+/// visited at most once, so a lookup costs the path length when no fallback
+/// is needed and at worst the number of route segments sharing the prefix.
+///
+/// Routes only share tree nodes by segment type, so parameters at the same
+/// position may have different names in different routes (`/users/:id` and
+/// `/users/:user_id/posts`). Request paths are normalized according to the router's `RoutingConfig`
+/// first.
 #[derive(Default)]
 pub struct HttpRouter {
-    routes: HashMap<String, Route>,
+    root: Node,
+    routes: Vec<Route>,
+    normalization: RoutingConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String, ParamType),
+    /// A wildcard, optionally naming the parameter that captures the rest
+    /// of the path.
+    Wildcard(Option<String>),
+}
+
+/// The values a path parameter accepts, written `:name<type>` in a route.
+#[derive(Debug, Clone)]
+pub enum ParamType {
+    /// `:name`: any segment.
+    Any,
+    /// `:name<int>`: ASCII digits.
+    Int,
+    /// `:name<uuid>`: a hyphenated UUID in either case.
+    Uuid,
+    /// `:name<regex>`: a segment matching the whole regular expression.
+    Pattern(Regex),
+}
+
+impl ParamType {
+    fn parse(spec: &str) -> Result<Self> {
+        match spec {
+            "int" => Ok(Self::Int),
+            "uuid" => Ok(Self::Uuid),
+            pattern => Regex::new(&format!("^(?:{})$", pattern))
+                .map(Self::Pattern)
+                .with_context(|| format!("Invalid parameter pattern <{}>", pattern)),
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Int => !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()),
+            Self::Uuid => {
+                value.len() == 36
+                    && value.bytes().enumerate().all(|(i, b)| match i {
+                        8 | 13 | 18 | 23 => b == b'-',
+                        _ => b.is_ascii_hexdigit(),
+                    })
+            }
+            Self::Pattern(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl PartialEq for ParamType {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Pattern(a), Self::Pattern(b)) => a.as_str() == b.as_str(),
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => Ok(()),
+            Self::Int => f.write_str("<int>"),
+            Self::Uuid => f.write_str("<uuid>"),
+            Self::Pattern(regex) => {
+                // Strip the anchors added by `parse`
+                let pattern = regex.as_str();
+                write!(f, "<{}>", &pattern[4..pattern.len() - 2])
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    /// Typed parameters first, plain `:name` last.
+    params: Vec<ParamNode>,
+    wildcard: Option<Box<Node>>,
+    /// The routes ending here, most specific request conditions first.
+    routes: HashMap<MethodMatcher, Vec<Leaf>>,
+}
+
+struct ParamNode {
+    kind: ParamType,
+    node: Node,
+}
+
+/// A route ending at a node, with the names it gives to the path segments
+/// captured on the way there.
+struct Leaf {
+    /// Index into `HttpRouter::routes`.
+    route: usize,
+    /// One name per parameter, in path order, then the wildcard's name if
+    /// the route ends in one (`None` when it is unnamed).
+    names: Vec<Option<String>>,
+    wildcard: bool,
+}
+
+impl Leaf {
+    fn params(&self, captures: &[&str]) -> HashMap<String, String> {
+        let mut params: HashMap<_, _> = self
+            .names
+            .iter()
+            .zip(captures)
+            .filter_map(|(name, value)| Some((name.clone()?, value.to_string())))
+            .collect();
+        if let (true, Some(rest)) = (self.wildcard, captures.last()) {
+            params.insert(WILDCARD_PARAM.to_string(), rest.to_string());
+        }
+        params
+    }
 }
 
 impl HttpRouter {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_normalization(normalization: RoutingConfig) -> Self {
         Self {
-            routes: HashMap::new(),
+            normalization,
+            ..Self::default()
         }
     }
 
+    /// Adds a route for `config.method` on `path`, and on each of the
+    /// endpoint's prefix rewrites. Fails if that method and path are already
+    /// routed with the same request conditions.
     #[instrument(skip(self, handler))]
     pub fn add_route<H>(&mut self, path: &str, config: EndpointConfig, handler: H) -> Result<()>
     where
         H: HttpHandler + 'static,
     {
-        let pattern = path_to_regex(path)?;
-        let route = Route {
-            pattern,
+        let method = MethodMatcher::parse(&config.method)?;
+        let matcher = RequestMatcher::new(&config.matches).with_context(|| format!("Invalid conditions for route {}", path))?;
+        let index = self.routes.len();
+        let route = (&method, index, &config.matches, matcher.specificity());
+        self.insert(path, route)?;
+
+        for rewrite in &config.prefix_rewrites {
+            let to = rewrite.to.trim_end_matches('/');
+            let rest = path
+                .strip_prefix(to)
+                .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                .with_context(|| format!("Route {} does not start with rewritten prefix {}", path, rewrite.to))?;
+            self.insert(&format!("{}{}", rewrite.from.trim_end_matches('/'), rest), route)?;
+        }
+
+        self.routes.push(Route {
+            method,
+            matcher,
             handler: Arc::new(handler),
+            rate_limiter: config
+                .rate_limit
+                .as_ref()
+                .filter(|limit| limit.enabled)
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
             config,
-        };
+        });
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        path: &str,
+        (method, index, matches, specificity): (&MethodMatcher, usize, &RequestMatchConfig, usize),
+    ) -> Result<()> {
+        let mut segments = parse_path(path)?;
+        if self.normalization.trailing_slash != TrailingSlash::Strip && path.len() > 1 && path.ends_with('/') {
+            segments.push(Segment::Static(String::new()));
+        }
+
+        let mut names = Vec::new();
+        let mut wildcard = false;
+        let mut node = &mut self.root;
+        for segment in segments {
+            node = match segment {
+                Segment::Static(name) if self.normalization.case_sensitive => node.statics.entry(name).or_default(),
+                Segment::Static(name) => node.statics.entry(name.to_lowercase()).or_default(),
+                Segment::Param(name, kind) => {
+                    names.push(Some(name));
+                    let index = match node.params.iter().position(|param| param.kind == kind) {
+                        Some(index) => index,
+                        None => {
+                            // Keep the plain parameter, if any, last
+                            let index = match kind {
+                                ParamType::Any => node.params.len(),
+                                _ => node.params.iter().position(|p| matches!(p.kind, ParamType::Any)).unwrap_or(node.params.len()),
+                            };
+                            node.params.insert(index, ParamNode { kind, node: Node::default() });
+                            index
+                        }
+                    };
+                    &mut node.params[index].node
+                }
+                Segment::Wildcard(name) => {
+                    names.push(name);
+                    wildcard = true;
+                    node.wildcard.get_or_insert_with(Box::default)
+                }
+            };
+        }
+        let leaves = node.routes.entry(method.clone()).or_default();
+        if leaves.iter().any(|leaf| self.routes[leaf.route].config.matches == *matches) {
+            bail!("Duplicate route {} {}", method, path);
+        }
 
-        debug!(path = %path, "Adding route");
-        self.routes.insert(path.to_string(), route);
+        debug!(method = %method, path = %path, "Adding route");
+        let position = leaves
+            .iter()
+            .position(|leaf| self.routes[leaf.route].matcher.specificity() < specificity)
+            .unwrap_or(leaves.len());
+        leaves.insert(position, Leaf { route: index, names, wildcard });
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    pub fn match_route(&self, path: &str) -> Option<(&Route, HashMap<String, String>)> {
-        // Normalize path
-        let normalized_path = normalize_path(path);
-        
-        for route in self.routes.values() {
-            if let Some(captures) = route.pattern.captures(&normalized_path) {
-                let mut params = HashMap::new();
-                for name in route.pattern.capture_names().flatten() {
-                    if let Some(value) = captures.name(name) {
-                        params.insert(name.to_string(), value.as_str().to_string());
+    /// Finds the route for a request.
+    ///
+    /// Among routes ending at the same place, one for the exact method wins
+    /// over `ANY`, and `HEAD` requests fall back to `GET`. When the path
+    /// matches but no route accepts the method, the error lists the methods
+    /// that are allowed. In `redirect` trailing slash mode, a path that only
+    /// matches without its trailing slash yields the location to redirect to.
+    pub fn match_route(&self, method: &Method, path: &str) -> Result<(&Route, HashMap<String, String>), RoutingError> {
+        self.match_request(method, path, &RequestInfo::default())
+    }
+
+    /// Like `match_route`, additionally checking each route's conditions on
+    /// the request's host, headers, query and client address.
+    #[instrument(skip(self, request))]
+    pub fn match_request(
+        &self,
+        method: &Method,
+        path: &str,
+        request: &RequestInfo,
+    ) -> Result<(&Route, HashMap<String, String>), RoutingError> {
+        let normalized_path = normalize_path(path, &self.normalization);
+        match self.find(method, &normalized_path, request) {
+            Err(RoutingError::NotFound)
+                if self.normalization.trailing_slash == TrailingSlash::Redirect && normalized_path.len() > 1 =>
+            {
+                match normalized_path.strip_suffix('/') {
+                    Some(location) if !matches!(self.find(method, location, request), Err(RoutingError::NotFound)) => {
+                        Err(RoutingError::Redirect { location: location.to_string() })
+                    }
+                    _ => Err(RoutingError::NotFound),
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn find(
+        &self,
+        method: &Method,
+        path: &str,
+        request: &RequestInfo,
+    ) -> Result<(&Route, HashMap<String, String>), RoutingError> {
+        let exact = MethodMatcher::Only(method.clone());
+        let head_fallback = MethodMatcher::Only(Method::GET);
+        let preference = [Some(&exact), Some(&MethodMatcher::Any), (method == Method::HEAD).then_some(&head_fallback)];
+
+        let mut found = None;
+        let mut path_matched = false;
+        let mut allowed = Vec::new();
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let case_sensitive = self.normalization.case_sensitive;
+        walk(&self.root, (!path.is_empty()).then_some(path), case_sensitive, &mut Vec::new(), &mut |node, captures| {
+            let applicable = |leaves| self.applicable(leaves, request);
+            if let Some(leaf) = preference.iter().flatten().find_map(|wanted| node.routes.get(*wanted).and_then(|leaves| applicable(leaves))) {
+                found = Some((leaf.route, leaf.params(captures)));
+                return true;
+            }
+            for (matcher, leaves) in &node.routes {
+                if applicable(leaves).is_some() {
+                    path_matched = true;
+                    if let MethodMatcher::Only(method) = matcher {
+                        allowed.push(method.clone());
                     }
                 }
-                return Some((route, params));
             }
+            false
+        });
+
+        if let Some((index, params)) = found {
+            return Ok((&self.routes[index], params));
+        }
+        if !path_matched {
+            return Err(RoutingError::NotFound);
+        }
+
+        if allowed.contains(&Method::GET) {
+            allowed.push(Method::HEAD);
         }
-        None
+        allowed.push(Method::OPTIONS);
+        allowed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        allowed.dedup();
+        Err(RoutingError::MethodNotAllowed { allowed })
     }
 
-    pub fn routes(&self) -> &HashMap<String, Route> {
+    /// The first of `leaves` whose route's conditions hold for `request`.
+    fn applicable<'l>(&self, leaves: &'l [Leaf], request: &RequestInfo) -> Option<&'l Leaf> {
+        leaves.iter().find(|leaf| self.routes[leaf.route].matcher.matches(request))
+    }
+
+    /// Applies the router's normalization policy to a request path, as done
+    /// before matching.
+    pub fn normalize_path(&self, path: &str) -> String {
+        normalize_path(path, &self.normalization)
+    }
+
+    /// Every route, in the order it was added.
+    pub fn routes(&self) -> &[Route] {
         &self.routes
     }
 }
 
-fn normalize_path(path: &str) -> String {
-    // Remove trailing slash if present
-    let path = path.trim_end_matches('/');
-    
-    // Handle API versioning
-    if path.starts_with("/api/v1/") {
-        path.replace("/api/v1/", "/api/")
+/// Visits the nodes with routes that `path` leads to, most specific first,
+/// until `visit` returns true. Returns whether it did. `captures` holds the
+/// path segments matched by parameters on the way, and the rest of the path
+/// for a wildcard; the routes of a node name them.
+///
+/// `path` has no leading slash and is `None` once every segment has been
+/// consumed, so a trailing slash shows up as a final empty segment.
+fn walk<'n, 'p>(
+    node: &'n Node,
+    path: Option<&'p str>,
+    case_sensitive: bool,
+    captures: &mut Vec<&'p str>,
+    visit: &mut dyn FnMut(&'n Node, &[&'p str]) -> bool,
+) -> bool {
+    let Some(path) = path else {
+        return !node.routes.is_empty() && visit(node, captures);
+    };
+    let (segment, rest) = match path.split_once('/') {
+        Some((segment, rest)) => (segment, Some(rest)),
+        None => (path, None),
+    };
+
+    let child = if case_sensitive {
+        node.statics.get(segment)
     } else {
-        path.to_string()
+        node.statics.get(&segment.to_lowercase())
+    };
+    if let Some(child) = child {
+        if walk(child, rest, case_sensitive, captures, visit) {
+            return true;
+        }
+    }
+
+    let matching_params = node.params.iter().filter(|param| !segment.is_empty() && param.kind.matches(segment));
+    for param in matching_params {
+        captures.push(segment);
+        if walk(&param.node, rest, case_sensitive, captures, visit) {
+            return true;
+        }
+        captures.pop();
+    }
+
+    let Some(child) = &node.wildcard else {
+        return false;
+    };
+    if child.routes.is_empty() || path.is_empty() {
+        return false;
     }
+    captures.push(path);
+    let visited = visit(child, captures);
+    captures.pop();
+    visited
 }
 
-fn path_to_regex(path: &str) -> Result<Regex> {
-    let mut pattern = String::with_capacity(path.len() * 2);
-    pattern.push('^');
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut segments = Vec::with_capacity(parts.len());
 
-    for segment in path.split('/') {
-        pattern.push('/');
-        if segment.starts_with(':') {
-            let param_name = &segment[1..];
-            pattern.push_str(&format!("(?P<{}>\\w+)", param_name));
-        } else if segment == "*" {
-            pattern.push_str(".*");
+    for (i, part) in parts.iter().enumerate() {
+        let segment = if let Some(param) = part.strip_prefix(':') {
+            let (name, kind) = match param.split_once('<') {
+                Some((name, spec)) => {
+                    let spec = spec
+                        .strip_suffix('>')
+                        .with_context(|| format!("Route {} has an unterminated parameter type in :{}", path, param))?;
+                    (name, ParamType::parse(spec).with_context(|| format!("Invalid route {}", path))?)
+                }
+                None => (param, ParamType::Any),
+            };
+            if name.is_empty() {
+                bail!("Route {} has a parameter without a name", path);
+            }
+            Segment::Param(name.to_string(), kind)
+        } else if let Some(name) = part.strip_prefix('*') {
+            if i + 1 != parts.len() {
+                bail!("Route {} has a wildcard before its last segment", path);
+            }
+            Segment::Wildcard((!name.is_empty()).then(|| name.to_string()))
         } else {
-            pattern.push_str(&regex::escape(segment));
+            Segment::Static(part.to_string())
+        };
+        segments.push(segment);
+    }
+
+    Ok(segments)
+}
+
+/// Normalizes a request path according to `config`: percent-decoding,
+/// then slash merging and dot-segment removal, then trailing slash
+/// handling. The result always starts with `/`.
+fn normalize_path(path: &str, config: &RoutingConfig) -> String {
+    let decoded = match config.percent_decoding {
+        PercentDecoding::None => path.to_string(),
+        PercentDecoding::Unreserved => percent_decode(path, is_unreserved),
+        PercentDecoding::All => percent_decode(path, |b| !matches!(b, b'/' | b'\\' | 0)),
+    };
+
+    let trailing_slash = decoded.len() > 1 && decoded.ends_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in decoded.trim_end_matches('/').split('/').skip(1) {
+        match segment {
+            "" if config.merge_slashes => {}
+            "." if config.remove_dot_segments => {}
+            ".." if config.remove_dot_segments => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
         }
     }
 
-    if !pattern.ends_with('$') {
-        pattern.push_str("/?$");  // Make trailing slash optional
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() && config.trailing_slash != TrailingSlash::Strip {
+        normalized.push('/');
+    }
+    normalized
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+/// Decodes the `%XX` escapes whose byte satisfies `decode`. Escapes that are
+/// kept are upper-cased, and if decoding yields invalid UTF-8 only
+/// unreserved characters are decoded.
+fn percent_decode(path: &str, decode: impl Fn(u8) -> bool) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if decode(byte) => decoded.push(byte),
+            Some(_) => decoded.extend(bytes[i..i + 3].to_ascii_uppercase()),
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+                continue;
+            }
+        }
+        i += 3;
     }
 
-    Regex::new(&pattern).context("Failed to compile route pattern")
+    String::from_utf8(decoded).unwrap_or_else(|_| percent_decode(path, is_unreserved))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::types::{BackendConfig, BackendProtocol};
+    use crate::config::types::{BackendConfig, PrefixRewrite};
+    use crate::protocol::http::{HttpClient, RequestInfo};
 
     #[test]
     fn test_path_normalization() {
-        assert_eq!(normalize_path("/api/v1/users"), "/api/users");
-        assert_eq!(normalize_path("/api/v1/users/"), "/api/users");
-        assert_eq!(normalize_path("/api/users"), "/api/users");
-        assert_eq!(normalize_path("/health"), "/health");
+        let config = RoutingConfig::default();
+        assert_eq!(normalize_path("/api/v1/users", &config), "/api/v1/users");
+        assert_eq!(normalize_path("/api/users/", &config), "/api/users");
+        assert_eq!(normalize_path("/health", &config), "/health");
+        assert_eq!(normalize_path("/", &config), "/");
+        assert_eq!(normalize_path("//a///b", &config), "/a/b");
+        assert_eq!(normalize_path("/a/./b/../c", &config), "/a/c");
+        assert_eq!(normalize_path("/../../etc/passwd", &config), "/etc/passwd");
+        assert_eq!(normalize_path("/a/%2e%2E/b", &config), "/b");
+        assert_eq!(normalize_path("/%7euser/a%2fb%3f", &config), "/~user/a%2Fb%3F");
+
+        let config = RoutingConfig {
+            trailing_slash: TrailingSlash::Strict,
+            merge_slashes: false,
+            percent_decoding: PercentDecoding::All,
+            remove_dot_segments: false,
+            case_sensitive: true,
+        };
+        assert_eq!(normalize_path("/a//b/../c/", &config), "/a//b/../c/");
+        assert_eq!(normalize_path("/caf%C3%A9/a%2Fb/%00", &config), "/café/a%2Fb/%00");
+        assert_eq!(normalize_path("/%FF", &config), "/%FF");
     }
 
     #[test]
-    fn test_path_to_regex() {
-        let cases = vec![
-            ("/users", "^/users/?$"),
-            ("/users/:id", "^/users/(?P<id>\\w+)/?$"),
-            ("/users/:id/posts", "^/users/(?P<id>\\w+)/posts/?$"),
-            ("/users/*", "^/users/.*/?$"),
-        ];
+    fn test_trailing_slash_and_case_policies() {
+        let routed = |routing: RoutingConfig, method: Method, path: &str| {
+            let mut router = HttpRouter::with_normalization(routing);
+            for route in ["/users", "/Docs/"] {
+                let config = endpoint("GET", route, "http://localhost:8080");
+                router.add_route(route, config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+            }
+            router.match_route(&method, path).map(|(route, _)| route.config.path.clone())
+        };
+
+        let strip = RoutingConfig::default();
+        assert_eq!(routed(strip.clone(), Method::GET, "/users/").unwrap(), "/users");
+        assert!(routed(strip, Method::GET, "/docs").is_err());
+
+        let strict = RoutingConfig {
+            trailing_slash: TrailingSlash::Strict,
+            case_sensitive: false,
+            ..RoutingConfig::default()
+        };
+        assert!(matches!(routed(strict.clone(), Method::GET, "/users/"), Err(RoutingError::NotFound)));
+        assert!(matches!(routed(strict.clone(), Method::GET, "/docs"), Err(RoutingError::NotFound)));
+        assert_eq!(routed(strict, Method::GET, "/DOCS/").unwrap(), "/Docs/");
 
-        for (path, expected) in cases {
-            let regex = path_to_regex(path).unwrap();
-            assert_eq!(regex.as_str(), expected);
+        let redirect = RoutingConfig {
+            trailing_slash: TrailingSlash::Redirect,
+            ..RoutingConfig::default()
+        };
+        match routed(redirect.clone(), Method::DELETE, "/a/../users/") {
+            Err(RoutingError::Redirect { location }) => assert_eq!(location, "/users"),
+            other => panic!("expected a redirect, got {:?}", other.map(|_| ())),
         }
+        assert!(matches!(routed(redirect, Method::GET, "/missing/"), Err(RoutingError::NotFound)));
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("/users/:id/*").unwrap(),
+            vec![
+                Segment::Static("users".to_string()),
+                Segment::Param("id".to_string(), ParamType::Any),
+                Segment::Wildcard(None),
+            ]
+        );
+        assert_eq!(
+            parse_path("/files/:n<int>/*rest").unwrap(),
+            vec![
+                Segment::Static("files".to_string()),
+                Segment::Param("n".to_string(), ParamType::Int),
+                Segment::Wildcard(Some("rest".to_string())),
+            ]
+        );
+        assert!(parse_path("/users/*/posts").is_err());
+        assert!(parse_path("/users/:").is_err());
+        assert!(parse_path("/users/:id<int").is_err());
+        assert!(parse_path("/users/:id<[a-z>").is_err());
     }
 
     #[test]
@@ -139,27 +655,169 @@ mod tests {
             backend: vec![BackendConfig {
                 url: "http://users-service:8080/users".to_string(),
                 method: Some("GET".to_string()),
-                timeout: None,
-                circuit_breaker: None,
-                retry: None,
-                protocol: BackendProtocol::Rest,
+                ..Default::default()
+            }],
+            prefix_rewrites: vec![PrefixRewrite {
+                from: "/api/v1".to_string(),
+                to: "/api".to_string(),
             }],
-            timeout: None,
-            cache_ttl: None,
-            rate_limit: None,
-            auth_required: false,
-            protocol: crate::config::types::GatewayProtocol::Rest,
-            guards: vec![],
+            ..Default::default()
         };
 
-        router.add_route("/api/users/:id", config.clone(), HttpClient::new(vec![config.backend[0].clone()]).unwrap()).unwrap();
+        router.add_route("/api/users/:id", config.clone(), HttpClient::new(&config.name(), vec![config.backend[0].clone()]).unwrap()).unwrap();
 
         // Test v1 path
-        let (_, params) = router.match_route("/api/v1/users/123").unwrap();
+        let (_, params) = router.match_route(&Method::GET, "/api/v1/users/123").unwrap();
         assert_eq!(params.get("id").unwrap(), "123");
 
         // Test direct path
-        let (_, params) = router.match_route("/api/users/456").unwrap();
+        let (_, params) = router.match_route(&Method::GET, "/api/users/456").unwrap();
         assert_eq!(params.get("id").unwrap(), "456");
     }
+
+    #[test]
+    fn test_method_aware_matching() {
+        let mut router = HttpRouter::new();
+        for (method, url) in [("GET", "http://read:8080"), ("delete", "http://write:8080"), ("ANY", "http://any:8080")] {
+            let path = if method == "ANY" { "/other/:id" } else { "/users/:id" };
+            let config = endpoint(method, path, url);
+            router.add_route(path, config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+        }
+
+        let backend = |method: Method, path: &str| {
+            router.match_route(&method, path).map(|(route, _)| route.config.backend[0].url.clone())
+        };
+        assert_eq!(backend(Method::GET, "/users/1").unwrap(), "http://read:8080");
+        assert_eq!(backend(Method::DELETE, "/users/1").unwrap(), "http://write:8080");
+        assert_eq!(backend(Method::HEAD, "/users/1").unwrap(), "http://read:8080");
+        assert_eq!(backend(Method::PATCH, "/other/1").unwrap(), "http://any:8080");
+        assert!(matches!(backend(Method::GET, "/missing"), Err(RoutingError::NotFound)));
+
+        match backend(Method::PATCH, "/users/1") {
+            Err(RoutingError::MethodNotAllowed { allowed }) => assert_eq!(
+                allowed,
+                vec![Method::DELETE, Method::GET, Method::HEAD, Method::OPTIONS]
+            ),
+            other => panic!("expected 405, got {:?}", other),
+        }
+
+        let config = endpoint("GET", "/users/:id", "http://again:8080");
+        assert!(router.add_route("/users/:id", config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_precedence_is_static_param_wildcard() {
+        let mut router = HttpRouter::new();
+        for (method, path) in [("GET", "/users/*"), ("GET", "/users/:id"), ("GET", "/users/me"), ("DELETE", "/users/:id/posts")] {
+            let config = endpoint(method, path, "http://localhost:8080");
+            router.add_route(path, config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+        }
+
+        let matched = |method: Method, path: &str| {
+            router.match_route(&method, path).map(|(route, params)| (route.config.path.clone(), params))
+        };
+        assert_eq!(matched(Method::GET, "/users/me").unwrap().0, "/users/me");
+        let (path, params) = matched(Method::GET, "/users/42").unwrap();
+        assert_eq!(path, "/users/:id");
+        assert_eq!(params["id"], "42");
+        assert_eq!(matched(Method::GET, "/users/42/posts").unwrap().0, "/users/*");
+        assert_eq!(matched(Method::DELETE, "/users/me/posts").unwrap().0, "/users/:id/posts");
+        assert!(matches!(matched(Method::GET, "/users"), Err(RoutingError::NotFound)));
+    }
+
+    #[test]
+    fn test_typed_params() {
+        let mut router = HttpRouter::new();
+        for path in ["/items/:id", "/items/:n<int>", "/items/:uuid<uuid>", "/items/:slug<[a-z-]+>/info", "/files/*rest"] {
+            let config = endpoint("GET", path, "http://localhost:8080");
+            router.add_route(path, config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+        }
+
+        let matched = |path: &str| {
+            let (route, params) = router.match_route(&Method::GET, path).unwrap();
+            (route.config.path.clone(), params)
+        };
+        assert_eq!(matched("/items/42").0, "/items/:n<int>");
+        let (path, params) = matched("/items/6F9619FF-8B86-D011-B42D-00C04FC964FF");
+        assert_eq!(path, "/items/:uuid<uuid>");
+        assert_eq!(params["uuid"], "6F9619FF-8B86-D011-B42D-00C04FC964FF");
+        assert_eq!(matched("/items/user@example.com").0, "/items/:id");
+        assert_eq!(matched("/items/my-slug/info").1["slug"], "my-slug");
+        assert!(router.match_route(&Method::GET, "/items/My_Slug/info").is_err());
+        assert_eq!(matched("/files/a/b/c.txt").1["rest"], "a/b/c.txt");
+    }
+
+    #[test]
+    fn test_request_conditions_select_route() {
+        let mut router = HttpRouter::new();
+        let hosts = [vec![], vec!["*.example.com".to_string()], vec!["api.example.com".to_string()]];
+        for (i, hosts) in hosts.into_iter().enumerate() {
+            let mut config = endpoint("GET", "/users", &format!("http://backend-{}:8080", i));
+            config.matches.hosts = hosts;
+            router.add_route("/users", config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+        }
+        let mut config = endpoint("POST", "/users", "http://internal:8080");
+        config.matches.client_cidrs = vec!["10.0.0.0/8".to_string()];
+        router.add_route("/users", config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+
+        let backend = |method: Method, host: &str, client_ip: &str| {
+            let request = RequestInfo {
+                host: Some(host),
+                client_ip: Some(client_ip.parse().unwrap()),
+                ..RequestInfo::default()
+            };
+            router.match_request(&method, "/users", &request).map(|(route, _)| route.config.backend[0].url.clone())
+        };
+        assert_eq!(backend(Method::GET, "other.test", "10.0.0.1").unwrap(), "http://backend-0:8080");
+        assert_eq!(backend(Method::GET, "www.example.com", "10.0.0.1").unwrap(), "http://backend-1:8080");
+        assert_eq!(backend(Method::GET, "api.example.com", "10.0.0.1").unwrap(), "http://backend-2:8080");
+        assert_eq!(backend(Method::POST, "other.test", "10.0.0.1").unwrap(), "http://internal:8080");
+        match backend(Method::POST, "other.test", "192.168.1.1") {
+            Err(RoutingError::MethodNotAllowed { allowed }) => assert_eq!(allowed, vec![Method::GET, Method::HEAD, Method::OPTIONS]),
+            other => panic!("expected 405, got {:?}", other),
+        }
+
+        let config = endpoint("GET", "/users", "http://again:8080");
+        assert!(router.add_route("/users", config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_param_names_are_per_route() {
+        let mut router = HttpRouter::new();
+        for (method, path) in [
+            ("GET", "/users/:id"),
+            ("DELETE", "/users/:user_id"),
+            ("GET", "/users/:userId/orders"),
+            ("GET", "/users/:a/files/*"),
+            ("DELETE", "/users/:b/files/*rest"),
+        ] {
+            let config = endpoint(method, path, "http://localhost:8080");
+            router.add_route(path, config.clone(), HttpClient::new(&config.name(), config.backend).unwrap()).unwrap();
+        }
+
+        let params = |method: Method, path: &str| router.match_route(&method, path).unwrap().1;
+        assert_eq!(params(Method::GET, "/users/1"), HashMap::from([("id".to_string(), "1".to_string())]));
+        assert_eq!(params(Method::DELETE, "/users/2"), HashMap::from([("user_id".to_string(), "2".to_string())]));
+        assert_eq!(params(Method::GET, "/users/3/orders"), HashMap::from([("userId".to_string(), "3".to_string())]));
+        assert_eq!(params(Method::GET, "/users/4/files/a/b")["a"], "4");
+        assert_eq!(params(Method::DELETE, "/users/5/files/a/b")["rest"], "a/b");
+
+        let config = endpoint("GET", "/users/:other", "http://localhost:8080");
+        let error = router
+            .add_route("/users/:other", config.clone(), HttpClient::new(&config.name(), config.backend).unwrap())
+            .unwrap_err();
+        assert!(error.to_string().contains("Duplicate route GET /users/:other"), "{}", error);
+    }
+
+    fn endpoint(method: &str, path: &str, url: &str) -> EndpointConfig {
+        EndpointConfig {
+            path: path.to_string(),
+            method: method.to_string(),
+            backend: vec![BackendConfig {
+                url: url.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
 } 
\ No newline at end of file
@@ -2,146 +2,865 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use axum::{
     Router,
-    routing::{get, post, put, delete},
-    extract::{State, Json, OriginalUri},
-    response::IntoResponse,
-    http::StatusCode,
+    routing::get,
     body::Body,
+    extract::{ConnectInfo, State, Json, OriginalUri},
+    response::{IntoResponse, Response},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Version},
 };
-use serde::{Serialize, de::DeserializeOwned};
-use serde_json::Value;
+use http_body_util::Limited;
+use serde_json::{json, Value};
 use tokio::sync::RwLock;
-use tracing::{info, debug, error};
+use tracing::{info, error, warn};
 use anyhow::{Result, Context};
 
-use super::{HttpProtocol, HttpContext, HttpHandler, middleware::Middleware};
-use crate::config::types::Config;
+use super::problem::{error_response, X_REQUEST_ID};
+use super::{transform, HttpProtocol, HttpContext, HttpRequest, RequestInfo};
+use crate::config::template::WILDCARD_PARAM;
+use crate::config::types::{BodyTransform, Config, HealthConfig};
+use crate::core::{GatewayError, RoutingError, Shutdown};
 
 pub struct HttpServer {
     protocol: Arc<RwLock<HttpProtocol>>,
     config: Arc<Config>,
+    shutdown: Shutdown,
 }
 
 #[derive(Clone)]
 struct ServerState {
     protocol: Arc<RwLock<HttpProtocol>>,
+    shutdown: Shutdown,
+    max_request_size: usize,
+    /// Whether the health check lists every backend with its state.
+    health_details: bool,
 }
 
 impl HttpServer {
-    pub fn new(protocol: Arc<RwLock<HttpProtocol>>, config: Arc<Config>) -> Self {
-        Self { protocol, config }
+    pub fn new(protocol: Arc<RwLock<HttpProtocol>>, config: Arc<Config>, shutdown: Shutdown) -> Self {
+        Self { protocol, config, shutdown }
     }
 
+    /// Binds the configured address and serves until `shutdown` is
+    /// triggered and in-flight requests have drained, or the shutdown
+    /// timeout runs out.
     pub async fn start(&self) -> Result<()> {
-        let addr = SocketAddr::from(([0, 0, 0, 0], self.config.server.port));
+        let addr = (self.config.server.host.as_str(), self.config.server.port);
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind HTTP server to {}:{}", addr.0, addr.1))?;
         let state = ServerState {
             protocol: self.protocol.clone(),
+            shutdown: self.shutdown.clone(),
+            max_request_size: self.config.server.max_request_size,
+            health_details: self.config.observability.health.include_details,
         };
+        let app = app(state, &self.config.observability.health);
 
-        let mut app = Router::new()
-            .route("/health", get(health_check));
-
-        // Add configured routes based on their methods
-        for endpoint in &self.config.endpoints {
-            let path = endpoint.path.clone();
-            match endpoint.method.to_uppercase().as_str() {
-                "GET" => app = app.route(&path, get(handle_request)),
-                "POST" => app = app.route(&path, post(handle_request)),
-                "PUT" => app = app.route(&path, put(handle_request)),
-                "DELETE" => app = app.route(&path, delete(handle_request)),
-                _ => continue,
-            };
-        }
+        // Keep accepting during the drain delay so load balancers see the
+        // health check fail before connections are refused.
+        let drain_delay = self.config.server.drain_delay;
+        let stop_accepting = {
+            let shutdown = self.shutdown.clone();
+            async move {
+                shutdown.triggered().await;
+                info!(delay = ?drain_delay, "Draining HTTP server");
+                tokio::time::sleep(drain_delay).await;
+            }
+        };
+        let deadline = {
+            let shutdown = self.shutdown.clone();
+            let timeout = self.config.server.shutdown_timeout;
+            async move {
+                shutdown.triggered().await;
+                tokio::time::sleep(drain_delay + timeout).await;
+            }
+        };
 
-        let app = app.with_state(state);
+        info!("HTTP server listening on {}", listener.local_addr()?);
+        let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(stop_accepting);
 
-        info!("Starting HTTP server on {}", addr);
-        axum::serve(
-            tokio::net::TcpListener::bind(&addr).await?,
-            app.into_make_service(),
-        )
-        .await
-        .context("Failed to start HTTP server")?;
+        tokio::select! {
+            result = server => result.context("HTTP server failed")?,
+            _ = deadline => warn!("Shutdown timeout elapsed, closing remaining connections"),
+        }
 
+        info!("HTTP server stopped");
         Ok(())
     }
 }
 
-async fn health_check() -> impl IntoResponse {
-    StatusCode::OK
+/// The health check, at its configured path when enabled, in front of the
+/// configured endpoints. Those are resolved by `HttpRouter` at request time
+/// rather than registered with axum, so reloaded routes take effect without
+/// rebuilding the server.
+fn app(state: ServerState, health: &HealthConfig) -> Router {
+    let mut app = Router::new();
+    if health.enabled {
+        app = app.route(&health.path, get(health_check));
+    }
+    app.fallback(handle_request).with_state(state)
+}
+
+/// Reports whether the gateway takes traffic and, with `include_details`,
+/// the state of every backend. Unhealthy or ejected backends and open
+/// circuits do not fail the check: the gateway itself is still able to serve.
+async fn health_check(State(state): State<ServerState>) -> impl IntoResponse {
+    let (status, draining) = if state.shutdown.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    if !state.health_details {
+        return (status, Json(json!({ "status": draining })));
+    }
+
+    let backends: Vec<_> = {
+        let protocol = state.protocol.read().await;
+        protocol
+            .router_ref()
+            .routes()
+            .iter()
+            .flat_map(|route| {
+                route.handler.backends().iter().map(|backend| {
+                    json!({
+                        "endpoint": backend.endpoint(),
+                        "url": backend.url(),
+                        "healthy": backend.is_healthy(),
+                        "ejected": backend.is_ejected(),
+                        "circuit": backend.circuit_breaker.as_ref().map(|breaker| breaker.state().as_str()),
+                    })
+                })
+            })
+            .collect()
+    };
+
+    (status, Json(json!({ "status": draining, "backends": backends })))
 }
 
 async fn handle_request(
     State(state): State<ServerState>,
+    method: Method,
+    version: Version,
     OriginalUri(uri): OriginalUri,
-    payload: Option<Json<Value>>,
-) -> Result<Json<Value>, StatusCode> {
-    let protocol_guard = state.protocol.read().await;
-    let path = uri.path();
-    let (route, params) = protocol_guard
-        .router_ref()
-        .match_route(path)
-        .ok_or(StatusCode::NOT_FOUND)?;
-    let route = route.clone();
-    let middlewares: Vec<_> = protocol_guard.middleware().iter().collect();
+    client: Option<ConnectInfo<SocketAddr>>,
+    mut headers: HeaderMap,
+    body: Body,
+) -> Result<Response, Response> {
+    let peer = client.map(|ConnectInfo(addr)| addr.ip());
+    // HTTP/2 requests carry the host in the URI rather than a header
+    let host = uri
+        .authority()
+        .map(|authority| authority.to_string())
+        .or_else(|| headers.get(header::HOST).and_then(|host| host.to_str().ok()).map(str::to_string));
+
+    // Keep the ID a client or an upstream proxy assigned, so logs can be
+    // correlated across hops
+    let request_id = headers
+        .get(X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    if let Ok(id) = HeaderValue::from_str(&request_id) {
+        headers.insert(X_REQUEST_ID, id);
+    }
+
+    // Take a snapshot of the route and middleware and release the lock
+    // before doing any I/O, so a config reload never waits on slow backends.
+    let (route, params, path, middlewares, forwarding, authenticator, client_ip) = {
+        let protocol_guard = state.protocol.read().await;
+        let forwarding = protocol_guard.forwarding().clone();
+        let client_ip = peer.map(|peer| forwarding.client_ip(peer, &headers));
+        let request = RequestInfo {
+            host: host.as_deref(),
+            headers: Some(&headers),
+            query: uri.query(),
+            client_ip,
+        };
+        let (route, params) = match protocol_guard.router_ref().match_request(&method, uri.path(), &request) {
+            Ok(matched) => matched,
+            Err(RoutingError::MethodNotAllowed { allowed }) => {
+                // Unrouted OPTIONS requests are answered here with the
+                // methods the path supports.
+                let mut response = if method == Method::OPTIONS {
+                    StatusCode::NO_CONTENT.into_response()
+                } else {
+                    error_response(&GatewayError::MethodNotAllowed, uri.path(), &request_id, &[])
+                };
+                response.headers_mut().insert(header::ALLOW, allow_header(&allowed));
+                return Err(response);
+            }
+            Err(RoutingError::Redirect { location }) => {
+                let location = match uri.query() {
+                    Some(query) => format!("{}?{}", location, query),
+                    None => location,
+                };
+                return Err(match HeaderValue::try_from(location) {
+                    Ok(location) => (StatusCode::PERMANENT_REDIRECT, [(header::LOCATION, location)]).into_response(),
+                    Err(_) => error_response(&GatewayError::BadRequest, uri.path(), &request_id, &[]),
+                });
+            }
+            Err(_) => return Err(error_response(&GatewayError::NotFound, uri.path(), &request_id, &[])),
+        };
+        let path = protocol_guard.router_ref().normalize_path(uri.path());
+        let middlewares: Vec<_> = protocol_guard.middleware().iter().cloned().collect();
+        let authenticator = protocol_guard.authenticator().cloned();
+        (route.clone(), params, path, middlewares, forwarding, authenticator, client_ip)
+    };
+    let fail = |error: GatewayError| error_response(&error, uri.path(), &request_id, &route.config.error_templates);
+
+    // Limited before anything else, so rejected requests cost neither a
+    // token check nor reading their body
+    if let Some(limiter) = &route.rate_limiter {
+        if let Err(error) = limiter.check(client_ip) {
+            return Err(fail(error));
+        }
+    }
+
+    if route.config.auth_required {
+        // Without security.auth there is nothing to check credentials with
+        let authorized = authenticator
+            .map_or(Err(GatewayError::Unauthorized), |auth| auth.authorize(&headers, &route.config.guards));
+        if let Err(error) = authorized {
+            return Err(fail(error));
+        }
+    }
+
+    let scheme = uri.scheme_str().unwrap_or("http");
+    forwarding.forward_request(&mut headers, peer, scheme, host.as_deref(), version);
 
+    // Bodies stream straight through to the backend, so the size limit is
+    // enforced while reading rather than up front. A declared length that
+    // is already too large is refused before contacting the backend.
+    let declared_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if declared_length.is_some_and(|length| length > state.max_request_size as u64) {
+        return Err(fail(GatewayError::PayloadTooLarge));
+    }
+    let mut request = HttpRequest {
+        method,
+        path,
+        query: uri.query().map(str::to_string),
+        params,
+        headers,
+        client_ip,
+        body: Body::new(Limited::new(body, state.max_request_size)),
+    };
+
+    // The wildcard capture is only kept for the backend URL; middleware
+    // sees the parameters named in the route
     let mut context = HttpContext::new();
-    for (k, v) in params {
-        context.insert(k, v);
+    for (k, v) in request.params.iter().filter(|(k, _)| k.as_str() != WILDCARD_PARAM) {
+        context.insert(k.clone(), v.clone());
     }
-    
+
+    // Middleware only sees parsed bodies on endpoints using the JSON
+    // transform, which has to buffer them; everything else is streamed
+    // without looking at the body.
+    let json = route.config.transform == BodyTransform::Json;
+    let payload_value = if json {
+        let (value, body) = transform::json_body(&mut request.headers, std::mem::take(&mut request.body))
+            .await
+            .map_err(|e| {
+                warn!(error = ?e, request_id = %request_id, "Rejecting request body");
+                match GatewayError::classify(&e) {
+                    GatewayError::Internal => fail(GatewayError::BadRequest),
+                    error => fail(error),
+                }
+            })?;
+        request.body = body;
+        value
+    } else {
+        Value::Null
+    };
+
     // Pre-process
-    let payload_value = payload.map(|p| p.0).unwrap_or(Value::Null);
     for middleware in &middlewares {
         if let Err(e) = middleware.pre_process(&payload_value, &mut context).await {
-            error!(?e, "Middleware pre-processing failed");
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            error!(?e, request_id = %request_id, "Middleware pre-processing failed");
+            return Err(fail(GatewayError::classify(&e)));
         }
     }
 
-    // Execute handler
-    let response = route
+    // Execute handler. Backend responses, errors included, are relayed
+    // as-is; only failures to get one become gateway errors.
+    let mut response = route
         .handler
-        .handle(payload_value)
+        .handle(request)
         .await
         .map_err(|e| {
-            error!(?e, "Request handler failed");
-            StatusCode::INTERNAL_SERVER_ERROR
+            error!(?e, request_id = %request_id, "Request handler failed");
+            fail(GatewayError::classify(&e))
         })?;
+    forwarding.forward_response(&mut response.headers, response.version);
+
+    let response_value = if json {
+        let (value, body) = transform::json_body(&mut response.headers, response.body)
+            .await
+            .map_err(|e| {
+                error!(?e, request_id = %request_id, "Backend returned an invalid JSON body");
+                fail(GatewayError::UpstreamFailed)
+            })?;
+        response.body = body;
+        value
+    } else {
+        Value::Null
+    };
 
     // Post-process
     for middleware in middlewares.iter().rev() {
-        if let Err(e) = middleware.post_process(&response, &mut context).await {
-            error!(?e, "Middleware post-processing failed");
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        if let Err(e) = middleware.post_process(&response_value, &mut context).await {
+            error!(?e, request_id = %request_id, "Middleware post-processing failed");
+            return Err(fail(GatewayError::classify(&e)));
         }
     }
 
-    Ok(Json(response))
+    if let Ok(id) = HeaderValue::from_str(&request_id) {
+        response.headers.entry(X_REQUEST_ID).or_insert(id);
+    }
+    let mut relayed = Response::new(response.body);
+    *relayed.status_mut() = response.status;
+    *relayed.headers_mut() = response.headers;
+    Ok(relayed)
+}
+
+fn allow_header(allowed: &[Method]) -> HeaderValue {
+    let methods: Vec<_> = allowed.iter().map(Method::as_str).collect();
+    HeaderValue::from_str(&methods.join(", ")).expect("method names are valid header values")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
     use axum::http::Request;
+    use axum::body::{Body, Bytes};
+    use http_body::Frame;
+    use http_body_util::BodyExt;
+    use tokio::sync::mpsc;
     use tower::ServiceExt;
-    use serde_json::json;
+    use super::super::middleware::{LoggingMiddleware, Middleware, MetricsMiddleware};
+
+    /// A body whose frames are sent by the test as it goes.
+    struct ChannelBody(mpsc::Receiver<Frame<Bytes>>);
+
+    impl http_body::Body for ChannelBody {
+        type Data = Bytes;
+        type Error = std::convert::Infallible;
+
+        fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+            self.0.poll_recv(cx).map(|frame| frame.map(Ok))
+        }
+    }
+
+    fn health(enabled: bool, include_details: bool) -> HealthConfig {
+        HealthConfig {
+            enabled,
+            path: "/healthz".to_string(),
+            include_details,
+            checks: vec![],
+        }
+    }
+
+    fn server_state(protocol: HttpProtocol, health_details: bool) -> ServerState {
+        ServerState {
+            protocol: Arc::new(RwLock::new(protocol)),
+            shutdown: Shutdown::new(),
+            max_request_size: 1024,
+            health_details,
+        }
+    }
 
     #[tokio::test]
     async fn test_health_check() {
-        let state = ServerState {
-            protocol: Arc::new(RwLock::new(HttpProtocol::new())),
+        let state = server_state(HttpProtocol::new(), false);
+        let app = app(state.clone(), &health(true, false));
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), json!({ "status": "ok" }));
+
+        state.shutdown.trigger();
+        let response = app
+            .oneshot(Request::builder().uri("/healthz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_can_be_disabled() {
+        let app = app(server_state(HttpProtocol::new(), false), &health(false, false));
+        for path in ["/health", "/healthz"] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed_lists_allowed_methods() {
+        let mut protocol = HttpProtocol::new();
+        let config: crate::config::types::EndpointConfig = serde_json::from_value(json!({
+            "path": "/users",
+            "method": "GET",
+            "backend": [{ "url": "http://localhost:8080" }],
+        }))
+        .unwrap();
+        let client = super::super::HttpClient::new(&config.name(), config.backend.clone()).unwrap();
+        protocol.router().add_route("/users", config, client).unwrap();
+
+        let app = Router::new().fallback(handle_request).with_state(server_state(protocol, false));
+
+        for (method, status) in [(Method::DELETE, StatusCode::METHOD_NOT_ALLOWED), (Method::OPTIONS, StatusCode::NO_CONTENT)] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().method(method).uri("/users").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+        }
+    }
+
+    /// Serves `app` on an ephemeral port and returns its base URL.
+    async fn spawn_backend(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn proxy_app(endpoint: Value) -> Router {
+        let mut protocol = HttpProtocol::new();
+        let config: crate::config::types::EndpointConfig = serde_json::from_value(endpoint).unwrap();
+        let mut client = super::super::HttpClient::new(&config.name(), config.backend.clone()).unwrap().with_load_balancing(config.load_balancing.clone());
+        if let Some(outlier_detection) = config.outlier_detection.clone() {
+            client = client.with_outlier_detection(outlier_detection);
+        }
+        protocol.router().add_route(&config.path.clone(), config, client).unwrap();
+        // The middleware a default configuration installs, so every proxy
+        // test, streaming included, runs through the chain
+        protocol.add_middleware(Middleware::Metrics(MetricsMiddleware));
+        protocol.add_middleware(Middleware::Logging(LoggingMiddleware));
+        protocol.start_health_checks();
+        let health = HealthConfig {
+            path: "/health".to_string(),
+            ..health(true, true)
         };
+        app(server_state(protocol, true), &health)
+    }
+
+    #[tokio::test]
+    async fn test_proxies_bytes_status_and_headers() {
+        let backend = spawn_backend(Router::new().fallback(|method: Method, headers: HeaderMap, body: axum::body::Bytes| async move {
+            let mut echoed = format!("{} {:?} ", method, headers.get("x-upload")).into_bytes();
+            echoed.extend_from_slice(&body);
+            (StatusCode::CREATED, [("x-backend", "files")], echoed)
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/files",
+            "method": "ANY",
+            "backend": [{ "url": backend }],
+        }));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri("/files")
+                    .header("x-upload", "1")
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .body(Body::from(vec![0u8, 159, 146, 150]))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["x-backend"], "files");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"PUT Some(\"1\") \x00\x9f\x92\x96");
+    }
+
+    #[tokio::test]
+    async fn test_json_transform() {
+        let backend = spawn_backend(Router::new().fallback(|body: axum::body::Bytes| async move {
+            match &body[..] {
+                b"{\"id\":1}" => (StatusCode::OK, "{ \"ok\": true }"),
+                _ => (StatusCode::OK, "not json"),
+            }
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "POST",
+            "backend": [{ "url": backend }],
+            "transform": "json",
+        }));
+        let post = |body: &'static str| Request::builder().method(Method::POST).uri("/orders").body(Body::from(body)).unwrap();
+
+        let response = app.clone().oneshot(post("{ \"id\": 1 }")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"{\"ok\":true}");
+
+        assert_eq!(app.clone().oneshot(post("{ \"id\": 2 }")).await.unwrap().status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(app.oneshot(post("<order/>")).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_applies_before_the_body_is_read() {
+        let backend = spawn_backend(Router::new().fallback(|body: axum::body::Bytes| async move { body })).await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "POST",
+            "backend": [{ "url": backend }],
+            "transform": "json",
+            "rate_limit": { "enabled": true, "requests_per_second": 1, "burst": 1 },
+        }));
+        let post = |body: Body| Request::builder().method(Method::POST).uri("/orders").body(body).unwrap();
+
+        let response = app.clone().oneshot(post(Body::from("{}"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The second body never arrives, yet the request is refused
+        let (_chunks, receiver) = mpsc::channel(1);
+        let response = tokio::time::timeout(std::time::Duration::from_secs(5), app.oneshot(post(Body::new(ChannelBody(receiver)))))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_streams_response_body() {
+        let (chunks, receiver) = mpsc::channel(1);
+        let receiver = Arc::new(std::sync::Mutex::new(Some(receiver)));
+        let backend = spawn_backend(Router::new().fallback(move || {
+            let receiver = receiver.lock().unwrap().take().unwrap();
+            async move { Body::new(ChannelBody(receiver)) }
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/events",
+            "method": "GET",
+            "backend": [{ "url": backend }],
+        }));
+
+        let response = app
+            .oneshot(Request::builder().uri("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let mut body = response.into_body();
+
+        // Each chunk reaches the client while the backend is still sending
+        for chunk in ["first", "second"] {
+            chunks.send(Frame::data(Bytes::from(chunk))).await.unwrap();
+            let frame = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            assert_eq!(frame.into_data().unwrap(), chunk);
+        }
+        drop(chunks);
+        assert!(body.frame().await.is_none());
+    }
 
-        let app = Router::new()
-            .route("/health", get(health_check))
-            .with_state(state);
+    #[tokio::test]
+    async fn test_relays_trailers() {
+        let backend = spawn_backend(Router::new().fallback(|| async {
+            let trailers = HeaderMap::from_iter([(header::HeaderName::from_static("x-checksum"), HeaderValue::from_static("abc"))]);
+            let (frames, receiver) = mpsc::channel(2);
+            frames.try_send(Frame::data(Bytes::from("data"))).unwrap();
+            frames.try_send(Frame::trailers(trailers)).unwrap();
+            ([(header::TRAILER, "x-checksum")], Body::new(ChannelBody(receiver)))
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/checked",
+            "method": "GET",
+            "backend": [{ "url": backend }],
+        }));
 
         let response = app
-            .oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap())
+            .oneshot(Request::builder().uri("/checked").header(header::TE, "trailers").body(Body::empty()).unwrap())
             .await
             .unwrap();
 
+        assert_eq!(response.headers()[header::TRAILER], "x-checksum");
+        let body = response.into_body().collect().await.unwrap();
+        assert_eq!(body.trailers().unwrap()["x-checksum"], "abc");
+        assert_eq!(&body.to_bytes()[..], b"data");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_request_body() {
+        let backend = spawn_backend(Router::new().fallback(|body: Bytes| async move { body.len().to_string() })).await;
+        let app = proxy_app(json!({
+            "path": "/upload",
+            "method": "POST",
+            "backend": [{ "url": backend }],
+        }));
+        let upload = || Request::builder().method(Method::POST).uri("/upload");
+
+        // A declared length over the limit is refused up front
+        let request = upload().header(header::CONTENT_LENGTH, "4096").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // A streamed body is cut off once it passes the limit
+        let (chunks, receiver) = mpsc::channel(4);
+        for _ in 0..4 {
+            chunks.send(Frame::data(Bytes::from(vec![b'x'; 512]))).await.unwrap();
+        }
+        drop(chunks);
+        let request = upload().body(Body::new(ChannelBody(receiver))).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let request = upload().body(Body::from(vec![b'x'; 1024])).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "1024");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_bodies_do_not_count_against_backends() {
+        let backend = spawn_backend(Router::new().fallback(|body: Bytes| async move { body.len().to_string() })).await;
+        let app = proxy_app(json!({
+            "path": "/upload",
+            "method": "POST",
+            "backend": [{
+                "url": backend,
+                "circuit_breaker": { "failure_rate_threshold": 50, "window": "10s", "min_requests": 1 },
+            }],
+            "outlier_detection": { "consecutive_5xx": 1, "max_ejection_percent": 100 },
+        }));
+
+        for _ in 0..3 {
+            let (chunks, receiver) = mpsc::channel(4);
+            for _ in 0..4 {
+                chunks.send(Frame::data(Bytes::from(vec![b'x'; 512]))).await.unwrap();
+            }
+            drop(chunks);
+            let request = Request::builder().method(Method::POST).uri("/upload").body(Body::new(ChannelBody(receiver))).unwrap();
+            assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let response = app.oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap()).await.unwrap();
+        let health: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(health["backends"][0]["ejected"], false);
+        assert_eq!(health["backends"][0]["circuit"], "closed");
+    }
+
+    #[tokio::test]
+    async fn test_maps_upstream_failures_to_problems() {
+        let backend = spawn_backend(Router::new()
+            .route("/missing", get(|| async { (StatusCode::NOT_FOUND, "no such order") }))
+            .route("/slow", get(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                "late"
+            })))
+        .await;
+        // Nothing listens on a port that was just released
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let get_status = |endpoint: Value| async move {
+            let response = proxy_app(endpoint)
+                .oneshot(Request::builder().uri("/orders").header("x-request-id", "abc").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+            assert_eq!(response.headers()["x-request-id"], "abc");
+            (status, content_type)
+        };
+
+        // Backend errors are relayed untouched
+        let (status, content_type) = get_status(json!({
+            "path": "/orders", "method": "GET",
+            "backend": [{ "url": format!("{}/missing", backend) }],
+        })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type.unwrap(), "text/plain; charset=utf-8");
+
+        let (status, content_type) = get_status(json!({
+            "path": "/orders", "method": "GET",
+            "backend": [{ "url": closed }],
+        })).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(content_type.unwrap(), "application/problem+json");
+
+        let (status, _) = get_status(json!({
+            "path": "/orders", "method": "GET",
+            "backend": [{ "url": format!("{}/slow", backend), "timeout": "100ms" }],
+        })).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+        let (status, _) = get_status(json!({
+            "path": "/orders", "method": "GET", "auth_required": true,
+            "backend": [{ "url": backend }],
+        })).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_fast() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let backend = spawn_backend(Router::new().fallback({
+            let calls = calls.clone();
+            move || async move {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "GET",
+            "backend": [{
+                "url": backend,
+                "circuit_breaker": { "failure_rate_threshold": 50, "window": "10s", "min_requests": 2 },
+            }],
+        }));
+        let get = |uri: &'static str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(app.clone().oneshot(get("/orders")).await.unwrap().status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let response = app.clone().oneshot(get("/orders")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let problem: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(problem["type"], "urn:rustopus:problem:circuit-open");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let response = app.oneshot(get("/health")).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let health: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(health["backends"][0]["endpoint"], "GET /orders");
+        assert_eq!(health["backends"][0]["circuit"], "open");
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests() {
+        // Every other call fails
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let backend = spawn_backend(Router::new().fallback({
+            let calls = calls.clone();
+            move || async move {
+                match calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) % 2 {
+                    0 => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::OK,
+                }
+            }
+        }))
+        .await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "ANY",
+            "backend": [{ "url": backend, "retry": { "attempts": 2, "backoff": "10ms" } }],
+        }));
+        let request = |method: Method| Request::builder().method(method).uri("/orders").body(Body::from("{}")).unwrap();
+
+        assert_eq!(app.clone().oneshot(request(Method::PUT)).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        assert_eq!(app.oneshot(request(Method::POST)).await.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_balances_across_backends() {
+        let a = spawn_backend(Router::new().fallback(|| async { "a" })).await;
+        let b = spawn_backend(Router::new().fallback(|| async { "b" })).await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "GET",
+            "backend": [{ "url": a }, { "url": b, "weight": 2 }],
+        }));
+
+        let mut served = String::new();
+        for _ in 0..6 {
+            let response = app.clone().oneshot(Request::builder().uri("/orders").body(Body::empty()).unwrap()).await.unwrap();
+            served.push_str(std::str::from_utf8(&response.into_body().collect().await.unwrap().to_bytes()).unwrap());
+        }
+        assert_eq!(served, "babbab");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unhealthy_backends_leave_rotation() {
+        let sick = spawn_backend(Router::new()
+            .route("/health", get(|| async { StatusCode::SERVICE_UNAVAILABLE }))
+            .fallback(|| async { "sick" }))
+        .await;
+        let well = spawn_backend(Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .fallback(|| async { "well" }))
+        .await;
+        // Paused time jumps ahead whenever the runtime waits on the backends,
+        // so the timeout leaves room for many jumps
+        let check = json!({ "interval": "20ms", "timeout": "1h", "unhealthy_threshold": 2 });
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "GET",
+            "backend": [{ "url": sick, "health_check": check }, { "url": well, "health_check": check }],
+        }));
+        let health = || async {
+            let response = app.clone().oneshot(Request::builder().uri("/health").body(Body::empty()).unwrap()).await.unwrap();
+            serde_json::from_slice::<Value>(&response.into_body().collect().await.unwrap().to_bytes()).unwrap()
+        };
+        while health().await["backends"][0]["healthy"] == true {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        for _ in 0..4 {
+            let response = app.clone().oneshot(Request::builder().uri("/orders").body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "well");
+        }
+
+        let health = health().await;
+        assert_eq!(health["backends"][0]["healthy"], false);
+        assert_eq!(health["backends"][1]["healthy"], true);
+    }
+
+    #[tokio::test]
+    async fn test_ejects_failing_backends() {
+        let failing = spawn_backend(Router::new().fallback(|| async { StatusCode::INTERNAL_SERVER_ERROR })).await;
+        let working = spawn_backend(Router::new().fallback(|| async { "ok" })).await;
+        let app = proxy_app(json!({
+            "path": "/orders",
+            "method": "GET",
+            "backend": [{ "url": failing }, { "url": working }],
+            "outlier_detection": { "consecutive_5xx": 2 },
+        }));
+
+        let mut statuses = Vec::new();
+        for _ in 0..8 {
+            let response = app.clone().oneshot(Request::builder().uri("/orders").body(Body::empty()).unwrap()).await.unwrap();
+            statuses.push(response.status().as_u16());
+        }
+        assert_eq!(statuses.iter().filter(|&&status| status == 500).count(), 2);
+        assert!(statuses[4..].iter().all(|&status| status == 200));
     }
-} 
\ No newline at end of file
+}
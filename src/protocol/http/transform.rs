@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use axum::body::Body;
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue};
+use http_body_util::{BodyExt, Full};
+use serde_json::Value;
+
+/// Reads `body` and parses it as JSON, returning the value and the compact
+/// re-encoding to send on, with trailers kept. The content headers are
+/// fixed up to match. Empty bodies are left alone and parse as `null`.
+pub async fn json_body(headers: &mut HeaderMap, body: Body) -> Result<(Value, Body)> {
+    let body = body.collect().await.context("Failed to read body")?;
+    let trailers = body.trailers().cloned();
+    let mut data = body.to_bytes();
+    let value = encode_json(headers, &mut data)?;
+    Ok((value, Body::new(Full::new(data).with_trailers(std::future::ready(trailers.map(Ok))))))
+}
+
+fn encode_json(headers: &mut HeaderMap, body: &mut Bytes) -> Result<Value> {
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    let value: Value = serde_json::from_slice(body).context("Body is not valid JSON")?;
+    *body = Bytes::from(serde_json::to_vec(&value)?);
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_json_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let (value, body) = json_body(&mut headers, Body::from("{ \"id\": 1 }")).await.unwrap();
+        assert_eq!(value, json!({ "id": 1 }));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "{\"id\":1}");
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(headers[header::CONTENT_LENGTH], "8");
+
+        let (value, body) = json_body(&mut HeaderMap::new(), Body::empty()).await.unwrap();
+        assert_eq!(value, Value::Null);
+        assert!(body.collect().await.unwrap().to_bytes().is_empty());
+
+        assert!(json_body(&mut HeaderMap::new(), Body::from("<xml/>")).await.is_err());
+    }
+}
@@ -20,4 +20,10 @@ impl Metrics {
     pub fn get_request_count(&self) -> u64 {
         self.request_count.load(Ordering::Relaxed)
     }
-} 
\ No newline at end of file
+} 
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
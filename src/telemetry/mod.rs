@@ -1,3 +1,5 @@
 pub mod metrics;
+pub mod prometheus;
 
-pub use metrics::Metrics; 
\ No newline at end of file
+pub use metrics::Metrics;
+pub use prometheus::MetricsServer;
@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use anyhow::{Context, Result};
+use axum::{routing::get, Router};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use parking_lot::Mutex;
+use tokio::net::TcpListener;
+use tracing::info;
+use crate::config::types::MetricsConfig;
+
+/// The recorder can only be installed once per process, so later calls, as
+/// made by tests or a gateway started twice, share the first handle.
+static RECORDER: Mutex<Option<PrometheusHandle>> = Mutex::new(None);
+
+/// Installs the Prometheus recorder that collects every metric of the
+/// gateway, or returns the handle of the one already installed. `tags` are
+/// added as labels to every metric; only those of the first call apply.
+pub fn install_recorder(config: &MetricsConfig) -> Result<PrometheusHandle> {
+    let mut recorder = RECORDER.lock();
+    if let Some(handle) = recorder.as_ref() {
+        return Ok(handle.clone());
+    }
+
+    let handle = config
+        .tags
+        .iter()
+        .fold(PrometheusBuilder::new(), |builder, (key, value)| builder.add_global_label(key, value))
+        .install_recorder()
+        .context("Failed to install the metrics recorder")?;
+    *recorder = Some(handle.clone());
+    Ok(handle)
+}
+
+/// Serves the collected metrics in the Prometheus text format on
+/// `metrics.path` of its own port, apart from proxied traffic.
+pub struct MetricsServer {
+    listener: TcpListener,
+    path: String,
+    handle: PrometheusHandle,
+}
+
+impl MetricsServer {
+    pub async fn bind(host: &str, config: &MetricsConfig) -> Result<Self> {
+        let handle = install_recorder(config)?;
+        let listener = TcpListener::bind((host, config.port))
+            .await
+            .with_context(|| format!("Failed to bind metrics server to {}:{}", host, config.port))?;
+        Ok(Self {
+            listener,
+            path: config.path.clone(),
+            handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves scrapes until `stop` resolves.
+    pub async fn serve(self, stop: impl Future<Output = ()> + Send + 'static) -> Result<()> {
+        let handle = self.handle;
+        let app = Router::new().route(&self.path, get(move || std::future::ready(handle.render())));
+
+        info!("Metrics server listening on {}{}", self.listener.local_addr()?, self.path);
+        axum::serve(self.listener, app)
+            .with_graceful_shutdown(stop)
+            .await
+            .context("Metrics server failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scrape() {
+        let config = MetricsConfig {
+            enabled: true,
+            port: 0,
+            path: "/prometheus".to_string(),
+            tags: Default::default(),
+        };
+        let server = MetricsServer::bind("127.0.0.1", &config).await.unwrap();
+        let url = format!("http://{}/prometheus", server.local_addr().unwrap());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(server.serve(async {
+            let _ = stopped.await;
+        }));
+
+        metrics::counter!("rustopus_test_scrapes_total", "endpoint" => "GET /users").increment(2);
+        let body = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        assert!(
+            body.contains(r#"rustopus_test_scrapes_total{endpoint="GET /users"} 2"#),
+            "{}",
+            body
+        );
+
+        stop.send(()).unwrap();
+        serving.await.unwrap().unwrap();
+    }
+}